@@ -1,5 +1,6 @@
-use calculating_pi_rust::pi_math::CalcPi;
+use calculating_pi_rust::pi_math::{self, closed_form_l_m_x, CalcPi};
 use criterion::{criterion_group, criterion_main, Criterion};
+use rug::Integer;
 
 fn calc_pi_with_write(c: &mut Criterion) {
     use std::fs::remove_dir_all;
@@ -7,7 +8,7 @@ fn calc_pi_with_write(c: &mut Criterion) {
     remove_dir_all(ben_path).unwrap_or(());
     c.bench_function("calc_pi_with_write", |b| {
         b.iter(|| {
-            let mut calc_pi = CalcPi::new(0, 1000, Some(ben_path));
+            let mut calc_pi = CalcPi::new(0, 1000, Some(ben_path)).unwrap();
             calc_pi.calc_pi_terms().unwrap();
         })
     });
@@ -19,11 +20,20 @@ fn calc_pi_wo_write(c: &mut Criterion) {
     remove_dir_all(ben_path).unwrap_or(());
     c.bench_function("calc_pi_wo_write", |b| {
         b.iter(|| {
-            let mut calc_pi = CalcPi::new(0, 1000, Some(ben_path));
+            let mut calc_pi = CalcPi::new(0, 1000, Some(ben_path)).unwrap();
             calc_pi.calc_pi_terms().unwrap();
         })
     });
 }
 
-criterion_group!(benches, calc_pi_with_write, calc_pi_wo_write);
+fn seed_l_m_x(c: &mut Criterion) {
+    let n = Integer::from(100_000);
+    let mut group = c.benchmark_group("seed_l_m_x");
+    group.sample_size(10);
+    group.bench_function("factorials", |b| b.iter(|| closed_form_l_m_x(&n)));
+    group.bench_function("prime_factors", |b| b.iter(|| pi_math::seed_l_m_x(&n)));
+    group.finish();
+}
+
+criterion_group!(benches, calc_pi_with_write, calc_pi_wo_write, seed_l_m_x);
 criterion_main!(benches);
@@ -0,0 +1,112 @@
+use std::fmt;
+use std::fs::{remove_file, rename, File};
+use std::io::{BufReader, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::data_handler::DataWriterState;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CheckpointError {
+    WriteError(String),
+    NoValidCheckpoint(String),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckpointError::WriteError(_s) => write!(f, "Could not write checkpoint: {}", _s),
+            CheckpointError::NoValidCheckpoint(_p) => {
+                write!(f, "No valid checkpoint found at {}", _p)
+            }
+        }
+    }
+}
+
+/// Everything `CalcPi` needs to carry on from the term after `last_n`.
+///
+/// Integers are stored as decimal strings, the same way they are written to
+/// the data files.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub n_start: i128,
+    pub n_end: i128,
+
+    pub last_n: String,
+    pub last_l: String,
+    pub last_m: String,
+    pub last_x: String,
+    pub k: String,
+    /// Time spent computing up to `last_n`, across every run so far.
+    pub compute_secs: f64,
+    /// Set for a job writing binary splitting triples, see `CalcPi::set_terms_per_triple`.
+    #[serde(default)]
+    pub terms_per_triple: Option<i128>,
+
+    pub writer: DataWriterState,
+}
+
+impl Checkpoint {
+    /// Atomically replaces the checkpoint at `path`, keeping the previous one
+    /// at `{path}.prev` in case the new one is found to be unusable.
+    pub fn save(&self, path: &str) -> Result<(), CheckpointError> {
+        let tmp_path = format!("{}.tmp", path);
+        let write = || -> std::io::Result<()> {
+            let mut file = File::create(&tmp_path)?;
+            file.write_all(serde_json::to_string(self)?.as_bytes())?;
+            file.sync_all()?;
+            if Path::new(path).exists() {
+                rename(path, format!("{}.prev", path))?;
+            }
+            rename(&tmp_path, path)
+        };
+        write().map_err(|e| CheckpointError::WriteError(e.to_string()))
+    }
+
+    /// Loads the newest checkpoint whose data file still holds every byte it
+    /// refers to.
+    pub fn load_latest(path: &str) -> Result<Checkpoint, CheckpointError> {
+        for candidate in [path.to_string(), format!("{}.prev", path)] {
+            if let Some(checkpoint) = Checkpoint::load_if_valid(&candidate) {
+                return Ok(checkpoint);
+            }
+            println!("Skipping unusable checkpoint {}", candidate);
+        }
+        Err(CheckpointError::NoValidCheckpoint(path.to_string()))
+    }
+
+    pub fn remove(path: &str) {
+        for candidate in [path.to_string(), format!("{}.prev", path)] {
+            remove_file(candidate).unwrap_or(());
+        }
+    }
+
+    /// Removes the checkpoint at `path` and those of the parts a threaded job
+    /// writes at `{path}.part{k}`.
+    pub fn remove_with_parts(path: &str) {
+        Checkpoint::remove(path);
+        for k in 0.. {
+            let part_path = format!("{}.part{}", path, k);
+            if !Path::new(&part_path).exists()
+                && !Path::new(&format!("{}.prev", part_path)).exists()
+            {
+                break;
+            }
+            Checkpoint::remove(&part_path);
+        }
+    }
+
+    fn load_if_valid(path: &str) -> Option<Checkpoint> {
+        let file = File::open(path).ok()?;
+        let checkpoint: Checkpoint = serde_json::from_reader(BufReader::new(file)).ok()?;
+        let data_len = Path::new(&checkpoint.writer.current_file_path())
+            .metadata()
+            .ok()?
+            .len();
+        if data_len < checkpoint.writer.file_len {
+            return None;
+        }
+        Some(checkpoint)
+    }
+}
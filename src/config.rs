@@ -0,0 +1,474 @@
+use serde::Deserialize;
+use std::fmt;
+use std::fs::read_to_string;
+use std::path::Path;
+
+/// Config file read when no path is given and `PI_CONFIG` is not set.
+pub const DEFAULT_CONFIG_PATH: &str = "./calculating_pi.toml";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    ReadError(String),
+    ParseError(usize, String),
+    InvalidValue(String, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::ReadError(_s) => write!(f, "Could not read config: {}", _s),
+            ConfigError::ParseError(_l, _s) => write!(f, "Config line {}: {}", _l, _s),
+            ConfigError::InvalidValue(_k, _v) => {
+                write!(f, "The value {} is not valid for {}", _v, _k)
+            }
+        }
+    }
+}
+
+/// Settings for a worker, read from a TOML file and then `PI_*` environment
+/// variables, which take precedence.
+///
+/// ```toml
+/// [api]
+/// url = "https://piapi.oscorp.ml"  # PI_API_URL
+/// timeout_secs = 60                # PI_API_TIMEOUT_SECS
+/// retry_count = 5                  # PI_API_RETRY_COUNT
+/// retry_delay_ms = 5000            # PI_API_RETRY_DELAY_MS, doubled every retry
+/// retry_max_delay_ms = 60000       # PI_API_RETRY_MAX_DELAY_MS
+/// retry_max_elapsed_secs = 300     # PI_API_RETRY_MAX_ELAPSED_SECS
+/// max_rejections = 10              # PI_API_MAX_REJECTIONS, jobs turned down before giving up
+/// rejection_backoff_ms = 60000     # PI_API_REJECTION_BACKOFF_MS, wait before giving up
+///
+/// [output]
+/// dir = "./"                       # PI_OUTPUT_DIR
+/// max_size_per_file = 2147483648   # PI_OUTPUT_MAX_SIZE_PER_FILE, in compressed bytes
+/// compression_level = 9            # PI_OUTPUT_COMPRESSION_LEVEL
+///
+/// [status]
+/// update_interval = 10000          # PI_STATUS_UPDATE_INTERVAL, defaults to the job's
+/// heartbeat_interval_ms = 15000    # PI_STATUS_HEARTBEAT_INTERVAL_MS, 0 disables heartbeats
+///
+/// [artifacts]
+/// store = "none"                   # PI_ARTIFACTS_STORE: none, local, http or s3
+/// location = "/stash/pi"           # PI_ARTIFACTS_LOCATION: a directory, or the base URL
+/// bucket = "pi"                    # PI_ARTIFACTS_BUCKET, s3 only
+/// region = "us-east-1"             # PI_ARTIFACTS_REGION, s3 only
+/// access_key = "..."               # PI_ARTIFACTS_ACCESS_KEY, s3 only
+/// secret_key = "..."               # PI_ARTIFACTS_SECRET_KEY, s3 only
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "ConfigFile")]
+pub struct WorkerConfig {
+    pub api_url: String,
+    pub timeout_secs: u64,
+    pub retry_count: u32,
+    pub retry_delay_ms: u64,
+    pub retry_max_delay_ms: u64,
+    pub retry_max_elapsed_secs: u64,
+    pub max_rejections: u32,
+    pub rejection_backoff_ms: u64,
+
+    pub output_dir: String,
+    pub max_size_per_file: u64,
+    pub compression_level: u32,
+
+    pub status_update_interval: Option<i128>,
+    pub heartbeat_interval_ms: u64,
+
+    pub artifact_store: String,
+    pub artifact_location: String,
+    pub artifact_bucket: String,
+    pub artifact_region: String,
+    pub artifact_access_key: String,
+    pub artifact_secret_key: String,
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        WorkerConfig {
+            api_url: "https://piapi.oscorp.ml".to_string(),
+            timeout_secs: 60,
+            retry_count: 5,
+            retry_delay_ms: 5000,
+            retry_max_delay_ms: 60_000,
+            retry_max_elapsed_secs: 300,
+            max_rejections: 10,
+            rejection_backoff_ms: 60_000,
+            output_dir: "./".to_string(),
+            max_size_per_file: 2_147_483_648,
+            compression_level: 9,
+            status_update_interval: None,
+            heartbeat_interval_ms: 15_000,
+            artifact_store: "none".to_string(),
+            artifact_location: String::new(),
+            artifact_bucket: String::new(),
+            artifact_region: "us-east-1".to_string(),
+            artifact_access_key: String::new(),
+            artifact_secret_key: String::new(),
+        }
+    }
+}
+
+impl WorkerConfig {
+    /// Loads the config file at `path`, or at `PI_CONFIG`, or at
+    /// `DEFAULT_CONFIG_PATH` if it exists, then applies environment overrides.
+    pub fn load(path: Option<&str>) -> Result<Self, ConfigError> {
+        let path = path
+            .map(|p| p.to_string())
+            .or_else(|| std::env::var("PI_CONFIG").ok())
+            .or_else(|| {
+                Path::new(DEFAULT_CONFIG_PATH)
+                    .exists()
+                    .then(|| DEFAULT_CONFIG_PATH.to_string())
+            });
+        let mut config = match path {
+            Some(path) => WorkerConfig::from_toml(
+                &read_to_string(&path).map_err(|e| ConfigError::ReadError(e.to_string()))?,
+            )?,
+            None => WorkerConfig::default(),
+        };
+        config.apply_env(|name| std::env::var(name).ok())?;
+        Ok(config)
+    }
+
+    /// Reads a config file, keeping the defaults for anything it leaves out.
+    pub fn from_toml(contents: &str) -> Result<Self, ConfigError> {
+        let config: WorkerConfig = toml::from_str(contents).map_err(|e| {
+            let line = e
+                .span()
+                .map_or(1, |span| contents[..span.start].matches('\n').count() + 1);
+            ConfigError::ParseError(line, e.message().to_string())
+        })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Applies `PI_SECTION_KEY` overrides looked up through `var`.
+    pub fn apply_env<F>(&mut self, var: F) -> Result<(), ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        for key in [
+            "api.url",
+            "api.timeout_secs",
+            "api.retry_count",
+            "api.retry_delay_ms",
+            "api.retry_max_delay_ms",
+            "api.retry_max_elapsed_secs",
+            "api.max_rejections",
+            "api.rejection_backoff_ms",
+            "output.dir",
+            "output.max_size_per_file",
+            "output.compression_level",
+            "status.update_interval",
+            "status.heartbeat_interval_ms",
+            "artifacts.store",
+            "artifacts.location",
+            "artifacts.bucket",
+            "artifacts.region",
+            "artifacts.access_key",
+            "artifacts.secret_key",
+        ] {
+            let name = format!("PI_{}", key.replace('.', "_").to_uppercase());
+            if let Some(value) = var(&name) {
+                self.set(key, value)?;
+            }
+        }
+        self.validate()
+    }
+
+    fn set(&mut self, key: &str, value: String) -> Result<(), ConfigError> {
+        match key {
+            "api.url" => self.api_url = value,
+            "api.timeout_secs" => self.timeout_secs = integer(key, value)?,
+            "api.retry_count" => self.retry_count = integer(key, value)?,
+            "api.retry_delay_ms" => self.retry_delay_ms = integer(key, value)?,
+            "api.retry_max_delay_ms" => self.retry_max_delay_ms = integer(key, value)?,
+            "api.retry_max_elapsed_secs" => self.retry_max_elapsed_secs = integer(key, value)?,
+            "api.max_rejections" => self.max_rejections = integer(key, value)?,
+            "api.rejection_backoff_ms" => self.rejection_backoff_ms = integer(key, value)?,
+            "output.dir" => self.output_dir = value,
+            "output.max_size_per_file" => self.max_size_per_file = integer(key, value)?,
+            "output.compression_level" => self.compression_level = integer(key, value)?,
+            "status.update_interval" => self.status_update_interval = Some(integer(key, value)?),
+            "status.heartbeat_interval_ms" => self.heartbeat_interval_ms = integer(key, value)?,
+            "artifacts.store" => self.artifact_store = value,
+            "artifacts.location" => self.artifact_location = value,
+            "artifacts.bucket" => self.artifact_bucket = value,
+            "artifacts.region" => self.artifact_region = value,
+            "artifacts.access_key" => self.artifact_access_key = value,
+            "artifacts.secret_key" => self.artifact_secret_key = value,
+            _ => unreachable!("{} is not a config key", key),
+        }
+        Ok(())
+    }
+
+    /// Checks the values whose type alone does not make them valid.
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.timeout_secs == 0 {
+            return Err(ConfigError::InvalidValue(
+                "api.timeout_secs".to_string(),
+                "0".to_string(),
+            ));
+        }
+        if self.max_size_per_file == 0 {
+            return Err(ConfigError::InvalidValue(
+                "output.max_size_per_file".to_string(),
+                "0".to_string(),
+            ));
+        }
+        if self.compression_level > 9 {
+            return Err(ConfigError::InvalidValue(
+                "output.compression_level".to_string(),
+                self.compression_level.to_string(),
+            ));
+        }
+        if let Some(interval) = self.status_update_interval.filter(|i| *i <= 0) {
+            return Err(ConfigError::InvalidValue(
+                "status.update_interval".to_string(),
+                interval.to_string(),
+            ));
+        }
+        if !["none", "local", "http", "s3"].contains(&self.artifact_store.as_str()) {
+            return Err(ConfigError::InvalidValue(
+                "artifacts.store".to_string(),
+                self.artifact_store.clone(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Parses the text of an environment variable, allowing `_` separators as
+/// TOML does.
+fn integer<T: std::str::FromStr>(key: &str, value: String) -> Result<T, ConfigError> {
+    value
+        .replace('_', "")
+        .parse()
+        .map_err(|_| ConfigError::InvalidValue(key.to_string(), value))
+}
+
+/// The layout of the config file. Every key is optional and falls back to
+/// `WorkerConfig::default`.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    api: ApiSection,
+    output: OutputSection,
+    status: StatusSection,
+    artifacts: ArtifactsSection,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ApiSection {
+    url: Option<String>,
+    timeout_secs: Option<u64>,
+    retry_count: Option<u32>,
+    retry_delay_ms: Option<u64>,
+    retry_max_delay_ms: Option<u64>,
+    retry_max_elapsed_secs: Option<u64>,
+    max_rejections: Option<u32>,
+    rejection_backoff_ms: Option<u64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct OutputSection {
+    dir: Option<String>,
+    max_size_per_file: Option<u64>,
+    compression_level: Option<u32>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct StatusSection {
+    update_interval: Option<i64>,
+    heartbeat_interval_ms: Option<u64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ArtifactsSection {
+    store: Option<String>,
+    location: Option<String>,
+    bucket: Option<String>,
+    region: Option<String>,
+    access_key: Option<String>,
+    secret_key: Option<String>,
+}
+
+impl From<ConfigFile> for WorkerConfig {
+    fn from(file: ConfigFile) -> Self {
+        let default = WorkerConfig::default();
+        let ConfigFile {
+            api,
+            output,
+            status,
+            artifacts,
+        } = file;
+        WorkerConfig {
+            api_url: api.url.unwrap_or(default.api_url),
+            timeout_secs: api.timeout_secs.unwrap_or(default.timeout_secs),
+            retry_count: api.retry_count.unwrap_or(default.retry_count),
+            retry_delay_ms: api.retry_delay_ms.unwrap_or(default.retry_delay_ms),
+            retry_max_delay_ms: api.retry_max_delay_ms.unwrap_or(default.retry_max_delay_ms),
+            retry_max_elapsed_secs: api
+                .retry_max_elapsed_secs
+                .unwrap_or(default.retry_max_elapsed_secs),
+            max_rejections: api.max_rejections.unwrap_or(default.max_rejections),
+            rejection_backoff_ms: api
+                .rejection_backoff_ms
+                .unwrap_or(default.rejection_backoff_ms),
+            output_dir: output.dir.unwrap_or(default.output_dir),
+            max_size_per_file: output
+                .max_size_per_file
+                .unwrap_or(default.max_size_per_file),
+            compression_level: output
+                .compression_level
+                .unwrap_or(default.compression_level),
+            status_update_interval: status
+                .update_interval
+                .map(i128::from)
+                .or(default.status_update_interval),
+            heartbeat_interval_ms: status
+                .heartbeat_interval_ms
+                .unwrap_or(default.heartbeat_interval_ms),
+            artifact_store: artifacts.store.unwrap_or(default.artifact_store),
+            artifact_location: artifacts.location.unwrap_or(default.artifact_location),
+            artifact_bucket: artifacts.bucket.unwrap_or(default.artifact_bucket),
+            artifact_region: artifacts.region.unwrap_or(default.artifact_region),
+            artifact_access_key: artifacts.access_key.unwrap_or(default.artifact_access_key),
+            artifact_secret_key: artifacts.secret_key.unwrap_or(default.artifact_secret_key),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_toml() {
+        let config = WorkerConfig::from_toml(
+            r#"
+                # Local coordinator
+                [api]
+                url = "http://localhost:8000"  # no trailing slash
+                retry_count = 2
+                max_rejections = 3
+
+                [output]
+                dir = "/scratch/pi#1"
+                max_size_per_file = 10_000_000
+                compression_level = 1
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.api_url, "http://localhost:8000");
+        assert_eq!(config.retry_count, 2);
+        assert_eq!(config.max_rejections, 3);
+        assert_eq!(config.rejection_backoff_ms, 60_000);
+        assert_eq!(config.timeout_secs, 60);
+        assert_eq!(config.output_dir, "/scratch/pi#1");
+        assert_eq!(config.max_size_per_file, 10_000_000);
+        assert_eq!(config.compression_level, 1);
+        assert_eq!(config.status_update_interval, None);
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        let mut config = WorkerConfig::from_toml("[api]\nurl = \"http://file\"").unwrap();
+        config
+            .apply_env(|name| match name {
+                "PI_API_URL" => Some("http://env".to_string()),
+                "PI_STATUS_UPDATE_INTERVAL" => Some("500".to_string()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(config.api_url, "http://env");
+        assert_eq!(config.status_update_interval, Some(500));
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        assert!(matches!(
+            WorkerConfig::from_toml("[api]\nurl_typo = \"x\""),
+            Err(ConfigError::ParseError(2, e)) if e.contains("unknown field `url_typo`")
+        ));
+        assert!(matches!(
+            WorkerConfig::from_toml("[cluster]\nnodes = 2"),
+            Err(ConfigError::ParseError(1, e)) if e.contains("unknown field `cluster`")
+        ));
+        assert_eq!(
+            WorkerConfig::from_toml("[output]\ncompression_level = 12"),
+            Err(ConfigError::InvalidValue(
+                "output.compression_level".to_string(),
+                "12".to_string()
+            ))
+        );
+        assert!(matches!(
+            WorkerConfig::from_toml("[api]\ntimeout_secs = \"60\""),
+            Err(ConfigError::ParseError(2, e)) if e.contains("invalid type: string \"60\"")
+        ));
+        assert_eq!(
+            WorkerConfig::from_toml("[artifacts]\nstore = \"ftp\""),
+            Err(ConfigError::InvalidValue(
+                "artifacts.store".to_string(),
+                "ftp".to_string()
+            ))
+        );
+        assert!(matches!(
+            WorkerConfig::from_toml("[api]\nurl"),
+            Err(ConfigError::ParseError(2, _))
+        ));
+        assert!(matches!(
+            WorkerConfig::from_toml("[api]\nretry_count = 1\nretry_count = 2"),
+            Err(ConfigError::ParseError(3, _))
+        ));
+    }
+
+    #[test]
+    fn test_zero_limits() {
+        assert_eq!(
+            WorkerConfig::from_toml("[api]\ntimeout_secs = 0"),
+            Err(ConfigError::InvalidValue(
+                "api.timeout_secs".to_string(),
+                "0".to_string()
+            ))
+        );
+        assert_eq!(
+            WorkerConfig::default().apply_env(|name| match name {
+                "PI_OUTPUT_MAX_SIZE_PER_FILE" => Some("0".to_string()),
+                _ => None,
+            }),
+            Err(ConfigError::InvalidValue(
+                "output.max_size_per_file".to_string(),
+                "0".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_update_interval_must_be_positive() {
+        for interval in ["0", "-5"] {
+            assert_eq!(
+                WorkerConfig::from_toml(&format!("[status]\nupdate_interval = {}", interval)),
+                Err(ConfigError::InvalidValue(
+                    "status.update_interval".to_string(),
+                    interval.to_string()
+                ))
+            );
+            assert_eq!(
+                WorkerConfig::default().apply_env(|name| match name {
+                    "PI_STATUS_UPDATE_INTERVAL" => Some(interval.to_string()),
+                    _ => None,
+                }),
+                Err(ConfigError::InvalidValue(
+                    "status.update_interval".to_string(),
+                    interval.to_string()
+                ))
+            );
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use std::fmt;
+use std::fs::{rename, File};
+use std::io::{BufReader, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::{get, patch, put};
+use axum::{Json, Router};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+
+use crate::artifact_store::Artifact;
+use crate::job_status::JobStatus;
+use crate::status_handler::{Heartbeat, JobInfo, NodeInfo, PStatusUpdate, SetStatus};
+
+#[derive(Debug)]
+pub enum CoordinatorError {
+    ReadError(String),
+    WriteError(String),
+}
+
+impl fmt::Display for CoordinatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CoordinatorError::ReadError(_s) => {
+                write!(f, "Could not read coordinator state: {}", _s)
+            }
+            CoordinatorError::WriteError(_s) => {
+                write!(f, "Could not write coordinator state: {}", _s)
+            }
+        }
+    }
+}
+
+/// A status a job was set to, and when.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StatusChange {
+    pub status: JobStatus,
+    pub at: String,
+    /// The error a worker reported with `JobStatus::Failed`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Where a worker uploaded the archive, reported with `JobStatus::Completed`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifact: Option<Artifact>,
+}
+
+/// A job in the queue, with everything workers have reported about it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JobRecord {
+    pub job: JobInfo,
+    /// `None` until the job is first offered.
+    pub status: Option<JobStatus>,
+    pub percentage_complete: f32,
+    pub node: Option<NodeInfo>,
+    pub history: Vec<StatusChange>,
+    /// When the job is taken back from its worker: an offered or accepted job
+    /// unless the worker moves it on, a running one unless it sends a heartbeat.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease_expires_at: Option<String>,
+}
+
+impl JobRecord {
+    /// Whether the job can be offered to a worker: never offered, rejected,
+    /// paused by a worker that was shut down, or taken back from a worker.
+    fn is_available(&self) -> bool {
+        matches!(
+            self.status,
+            None | Some(JobStatus::Rejected) | Some(JobStatus::Paused) | Some(JobStatus::Requeued)
+        )
+    }
+
+    fn lease_expired(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        self.lease_expires_at
+            .as_deref()
+            .and_then(|at| chrono::DateTime::parse_from_rfc3339(at).ok())
+            .is_some_and(|at| at < now)
+    }
+}
+
+const DEFAULT_LEASE_DURATION: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct CoordinatorState {
+    jobs: Vec<JobRecord>,
+}
+
+/// Serves the `/worker-nodes` API that `StatusHandler` talks to, from a job
+/// queue that is saved to `state_path` after every change.
+///
+/// A running job holds a lease that its worker renews with heartbeats. A job
+/// whose lease runs out, or is revoked, is requeued to be offered again, and
+/// its worker is told to stop at its next heartbeat. Offered and accepted
+/// jobs hold a lease too, so a worker that dies before starting one does not
+/// keep it. Only workers mark jobs failed.
+#[derive(Clone)]
+pub struct Coordinator {
+    state: Arc<Mutex<CoordinatorState>>,
+    state_path: Option<PathBuf>,
+    lease_duration: Duration,
+}
+
+impl Coordinator {
+    /// A coordinator that keeps its queue in memory only.
+    pub fn in_memory() -> Self {
+        Coordinator {
+            state: Arc::new(Mutex::new(CoordinatorState::default())),
+            state_path: None,
+            lease_duration: DEFAULT_LEASE_DURATION,
+        }
+    }
+
+    /// Loads the queue saved at `state_path`, or starts an empty one there.
+    pub fn open(state_path: &Path) -> Result<Self, CoordinatorError> {
+        let state = if state_path.exists() {
+            let file =
+                File::open(state_path).map_err(|e| CoordinatorError::ReadError(e.to_string()))?;
+            serde_json::from_reader(BufReader::new(file))
+                .map_err(|e| CoordinatorError::ReadError(e.to_string()))?
+        } else {
+            CoordinatorState::default()
+        };
+        Ok(Coordinator {
+            state: Arc::new(Mutex::new(state)),
+            state_path: Some(state_path.to_path_buf()),
+            lease_duration: DEFAULT_LEASE_DURATION,
+        })
+    }
+
+    /// How long a running job may go without a heartbeat.
+    pub fn with_lease_duration(mut self, lease_duration: Duration) -> Self {
+        self.lease_duration = lease_duration;
+        self
+    }
+
+    pub fn enqueue(&self, job: JobInfo) -> Result<(), CoordinatorError> {
+        let mut state = self.state.lock().unwrap();
+        state.jobs.push(JobRecord {
+            job,
+            status: None,
+            percentage_complete: 0.0,
+            node: None,
+            history: vec![],
+            lease_expires_at: None,
+        });
+        self.save(&state)
+    }
+
+    pub fn jobs(&self) -> Vec<JobRecord> {
+        self.state.lock().unwrap().jobs.clone()
+    }
+
+    /// Takes job `id` away from its worker to be offered again, returning
+    /// whether it was running.
+    pub fn revoke_lease(&self, id: u64) -> Result<bool, CoordinatorError> {
+        let mut state = self.state.lock().unwrap();
+        let record = match state.jobs.iter_mut().find(|record| record.job.id == id) {
+            Some(record) if record.status == Some(JobStatus::Running) => record,
+            _ => return Ok(false),
+        };
+        status_change(
+            record,
+            JobStatus::Requeued,
+            Some("lease revoked".to_string()),
+            None,
+        );
+        self.save(&state)?;
+        Ok(true)
+    }
+
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/worker-nodes/get-job", put(get_job))
+            .route("/worker-nodes/set-status", patch(set_status))
+            .route("/worker-nodes/set-info", patch(set_info))
+            .route("/worker-nodes/update-percentage", patch(update_percentage))
+            .route("/worker-nodes/heartbeat", patch(heartbeat))
+            .route("/worker-nodes/jobs", get(list_jobs))
+            .with_state(self.clone())
+    }
+
+    /// Serves the API on `listener` until the process exits.
+    pub async fn serve(&self, listener: TcpListener) -> std::io::Result<()> {
+        axum::serve(listener, self.router()).await
+    }
+
+    /// Binds `addr` and serves the API from a background task, returning the
+    /// address actually bound (useful with port 0).
+    pub async fn spawn(&self, addr: SocketAddr) -> std::io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let coordinator = self.clone();
+        tokio::spawn(async move { coordinator.serve(listener).await });
+        Ok(local_addr)
+    }
+
+    /// Applies `f` to the record of job `id` and saves the queue.
+    fn update_job<F>(&self, id: u64, f: F) -> Result<(), StatusCode>
+    where
+        F: FnOnce(&mut JobRecord),
+    {
+        let mut state = self.state.lock().unwrap();
+        let record = state
+            .jobs
+            .iter_mut()
+            .find(|record| record.job.id == id)
+            .ok_or(StatusCode::NOT_FOUND)?;
+        f(record);
+        self.save(&state)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    fn lease_expiry(&self) -> String {
+        let lease_duration =
+            chrono::Duration::from_std(self.lease_duration).unwrap_or(chrono::Duration::MAX);
+        (chrono::Utc::now() + lease_duration).to_rfc3339()
+    }
+
+    /// Requeues every job whose lease has run out, saving the queue if any did.
+    fn expire_leases(&self, state: &mut CoordinatorState) -> Result<(), StatusCode> {
+        let now = chrono::Utc::now();
+        let mut expired = false;
+        for record in state.jobs.iter_mut() {
+            if record.lease_expired(now) {
+                status_change(
+                    record,
+                    JobStatus::Requeued,
+                    Some("lease expired".to_string()),
+                    None,
+                );
+                expired = true;
+            }
+        }
+        if expired {
+            self.save(state)
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        }
+        Ok(())
+    }
+
+    /// Atomically replaces the saved queue.
+    fn save(&self, state: &CoordinatorState) -> Result<(), CoordinatorError> {
+        let state_path = match &self.state_path {
+            Some(state_path) => state_path,
+            None => return Ok(()),
+        };
+        let tmp_path = state_path.with_extension("tmp");
+        let write = || -> std::io::Result<()> {
+            let mut file = File::create(&tmp_path)?;
+            file.write_all(serde_json::to_string_pretty(state)?.as_bytes())?;
+            file.sync_all()?;
+            rename(&tmp_path, state_path)
+        };
+        write().map_err(|e| CoordinatorError::WriteError(e.to_string()))
+    }
+}
+
+/// Workers send JSON without a content type, so bodies are parsed by hand
+/// rather than with the `Json` extractor.
+fn parse_body<T: DeserializeOwned>(body: &Bytes) -> Result<T, StatusCode> {
+    serde_json::from_slice(body).map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)
+}
+
+fn status_change(
+    record: &mut JobRecord,
+    status: JobStatus,
+    message: Option<String>,
+    artifact: Option<Artifact>,
+) {
+    record.status = Some(status);
+    record.lease_expires_at = None;
+    record.history.push(StatusChange {
+        status,
+        at: chrono::Utc::now().to_rfc3339(),
+        message,
+        artifact,
+    });
+}
+
+/// Offers the first available job whose id is not in the body's list of ids
+/// the worker already turned down.
+async fn get_job(
+    State(coordinator): State<Coordinator>,
+    body: Bytes,
+) -> Result<Json<JobInfo>, StatusCode> {
+    let rejected_ids: Vec<u64> = parse_body(&body)?;
+    let mut state = coordinator.state.lock().unwrap();
+    coordinator.expire_leases(&mut state)?;
+    let record = state
+        .jobs
+        .iter_mut()
+        .find(|record| record.is_available() && !rejected_ids.contains(&record.job.id))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    status_change(record, JobStatus::Offered, None, None);
+    record.lease_expires_at = Some(coordinator.lease_expiry());
+    let job = record.job.clone();
+    coordinator
+        .save(&state)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(job))
+}
+
+async fn set_status(
+    State(coordinator): State<Coordinator>,
+    body: Bytes,
+) -> Result<StatusCode, StatusCode> {
+    let update: SetStatus = parse_body(&body)?;
+    let lease_expires_at = coordinator.lease_expiry();
+    let mut valid = false;
+    coordinator.update_job(update.id, |record| {
+        valid = record
+            .status
+            .is_some_and(|status| status.can_transition_to(update.status));
+        if valid {
+            status_change(
+                record,
+                update.status,
+                update.message.clone(),
+                update.artifact.clone(),
+            );
+            if matches!(update.status, JobStatus::Accepted | JobStatus::Running) {
+                record.lease_expires_at = Some(lease_expires_at);
+            }
+        }
+    })?;
+    if !valid {
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok(StatusCode::OK)
+}
+
+async fn set_info(
+    State(coordinator): State<Coordinator>,
+    body: Bytes,
+) -> Result<StatusCode, StatusCode> {
+    let node_info: NodeInfo = parse_body(&body)?;
+    coordinator.update_job(node_info.id, |record| record.node = Some(node_info.clone()))?;
+    Ok(StatusCode::OK)
+}
+
+async fn update_percentage(
+    State(coordinator): State<Coordinator>,
+    body: Bytes,
+) -> Result<StatusCode, StatusCode> {
+    let update: PStatusUpdate = parse_body(&body)?;
+    if !(0.0..=100.0).contains(&update.percentage_complete) {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    coordinator.update_job(update.id, |record| {
+        record.percentage_complete = update.percentage_complete
+    })?;
+    Ok(StatusCode::OK)
+}
+
+/// Renews the lease on a running job, or answers 410 Gone if the job is no
+/// longer running, so its worker stops.
+async fn heartbeat(
+    State(coordinator): State<Coordinator>,
+    body: Bytes,
+) -> Result<StatusCode, StatusCode> {
+    let heartbeat: Heartbeat = parse_body(&body)?;
+    let lease_expires_at = coordinator.lease_expiry();
+    let mut state = coordinator.state.lock().unwrap();
+    coordinator.expire_leases(&mut state)?;
+    let record = state
+        .jobs
+        .iter_mut()
+        .find(|record| record.job.id == heartbeat.id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let running = record.status == Some(JobStatus::Running);
+    if running {
+        record.lease_expires_at = Some(lease_expires_at);
+    }
+    coordinator
+        .save(&state)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !running {
+        return Err(StatusCode::GONE);
+    }
+    Ok(StatusCode::OK)
+}
+
+async fn list_jobs(
+    State(coordinator): State<Coordinator>,
+) -> Result<Json<Vec<JobRecord>>, StatusCode> {
+    let mut state = coordinator.state.lock().unwrap();
+    coordinator.expire_leases(&mut state)?;
+    Ok(Json(state.jobs.clone()))
+}
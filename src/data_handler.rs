@@ -1,15 +1,21 @@
 use std::fmt;
 use std::fmt::Debug;
-use std::fs::{create_dir, create_dir_all, File};
-use std::io::Write;
+use std::fs::{create_dir, create_dir_all, remove_dir, rename, File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
 use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use flate2::write::GzEncoder;
-use tar::Builder;
+use flate2::Compression;
+use rug::Integer;
+use serde::{Deserialize, Serialize};
+use tar::{Builder, Header};
+
+use crate::manifest::{Manifest, MANIFEST_NAME};
+use crate::output_format::{output_format_from_name, OutputFormat};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum HeaderError {
-    FileTypeNotSupported(String),
     HeaderAlreadyWritten(Vec<String>),
     TooLateToAddHeader(i32),
     HeaderNotInitialized(),
@@ -18,14 +24,14 @@ pub enum HeaderError {
 #[derive(Debug, PartialEq, Eq)]
 pub enum DataWriterError {
     FileAlreadyExists(String),
+    FileTypeNotSupported(String),
+    IoError(String),
+    Header(HeaderError),
 }
 
 impl fmt::Display for HeaderError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            HeaderError::FileTypeNotSupported(_s) => {
-                write!(f, "The file type {} does not support headers", _s)
-            }
             HeaderError::HeaderAlreadyWritten(_v) => {
                 write!(f, "The header: {:?} has already been written", _v)
             }
@@ -43,24 +49,87 @@ impl fmt::Display for DataWriterError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             DataWriterError::FileAlreadyExists(_s) => write!(f, "The file {} already exists", _s),
+            DataWriterError::FileTypeNotSupported(_s) => {
+                write!(f, "The file type {} does not support this write", _s)
+            }
+            DataWriterError::IoError(_s) => write!(f, "Could not write data: {}", _s),
+            DataWriterError::Header(_e) => write!(f, "{}", _e),
         }
     }
 }
 
+impl From<std::io::Error> for DataWriterError {
+    fn from(e: std::io::Error) -> Self {
+        DataWriterError::IoError(e.to_string())
+    }
+}
+
+impl From<HeaderError> for DataWriterError {
+    fn from(e: HeaderError) -> Self {
+        DataWriterError::Header(e)
+    }
+}
+
+impl From<DataWriterError> for std::io::Error {
+    fn from(e: DataWriterError) -> Self {
+        std::io::Error::other(e.to_string())
+    }
+}
+
+/// Position of a `DataWriter` at a term boundary, used to pick up the same
+/// output directory after a restart.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DataWriterState {
+    pub master_path: String,
+    pub file_type: String,
+    pub file_number: i32,
+    pub file_len: u64,
+
+    pub f_ln_written: i32,
+    pub t_ln_written: i32,
+    pub file_rows: Vec<u64>,
+
+    pub header_written: bool,
+    pub headers: Vec<String>,
+    pub header_assigned: bool,
+}
+
+impl DataWriterState {
+    pub fn current_file_path(&self) -> String {
+        format!(
+            "{}/data{}.{}.gz",
+            self.master_path, self.file_number, self.file_type
+        )
+    }
+}
+
 pub struct ArchiveInfo {
-    id: i32,
-    batch_id: i32,
+    id: u64,
+    batch_id: u64,
 }
 
+/// Writes rows to `data{N}.{file_type}.gz` files in an output directory,
+/// compressing as it goes so only compressed data ever reaches the disk.
+///
+/// Each file is a multi-member gzip stream: a member is ended whenever the
+/// state is taken, so a checkpointed length is always a valid place to
+/// truncate the file and carry on.
 pub struct DataWriter {
     master_path: String,
+    /// Used for the file's length and syncing; rows go through `encoder`.
     current_file: File,
+    /// The gzip member being written, started on the first write after the last one ended.
+    encoder: Option<GzEncoder<File>>,
     file_type: String,
+    format: Box<dyn OutputFormat>,
     file_number: i32,
 
     f_ln_written: i32,
     t_ln_written: i32,
+    /// Rows written to each data file, not counting headers.
+    file_rows: Vec<u64>,
     max_size_per_file: u64,
+    compression_level: u32,
 
     header_written: bool,
     headers: Vec<String>,
@@ -70,40 +139,148 @@ pub struct DataWriter {
 }
 
 impl DataWriter {
-    pub fn new(file_type: &str, base_file_path: Option<&str>) -> Self {
-        let master_path = DataWriter::create_output_dir(base_file_path).unwrap();
+    pub fn new(file_type: &str, base_file_path: Option<&str>) -> Result<Self, DataWriterError> {
+        let format = output_format_from_name(file_type)
+            .ok_or_else(|| DataWriterError::FileTypeNotSupported(file_type.to_string()))?;
+        let master_path = DataWriter::create_output_dir(base_file_path)?;
         let file_number = 0;
-        DataWriter {
+        let current_file = File::create(format!(
+            "{}/data{}.{}.gz",
+            &master_path, file_number, file_type
+        ))?;
+        Ok(DataWriter {
             master_path: master_path.clone(),
             file_number,
-            file_type: file_type.to_owned().parse().unwrap(),
-            current_file: File::create(format!(
-                "{}/data{}.{}",
-                &master_path, file_number, file_type
-            ))
-            .unwrap(),
+            file_type: file_type.to_owned(),
+            format,
+            current_file,
+            encoder: None,
             f_ln_written: 0,
+            file_rows: vec![0],
             max_size_per_file: 2_147_483_648,
-            // max_size_per_file: 10_000_000,
+            compression_level: 9,
             t_ln_written: 0,
             headers: Vec::new(),
             header_written: false,
             header_assigned: false,
 
             archive_info: None,
+        })
+    }
+
+    /// Reopens the output directory described by `state`, dropping anything
+    /// written to the current file after the state was taken.
+    pub fn resume(state: &DataWriterState) -> std::io::Result<Self> {
+        let format = output_format_from_name(&state.file_type).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("The file type {} is not supported", state.file_type),
+            )
+        })?;
+        if state.file_rows.len() != state.file_number as usize + 1 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "The row counts do not match the data files",
+            ));
         }
+        let mut current_file = OpenOptions::new()
+            .write(true)
+            .open(state.current_file_path())?;
+        current_file.set_len(state.file_len)?;
+        current_file.seek(SeekFrom::End(0))?;
+        Ok(DataWriter {
+            master_path: state.master_path.clone(),
+            current_file,
+            encoder: None,
+            file_type: state.file_type.clone(),
+            format,
+            file_number: state.file_number,
+            f_ln_written: state.f_ln_written,
+            t_ln_written: state.t_ln_written,
+            file_rows: state.file_rows.clone(),
+            max_size_per_file: 2_147_483_648,
+            compression_level: 9,
+            header_written: state.header_written,
+            headers: state.headers.clone(),
+            header_assigned: state.header_assigned,
+            archive_info: None,
+        })
     }
 
-    pub fn assign_headers(&mut self, headers: Vec<String>) -> Result<(), HeaderError> {
-        // Check if file supports headers
-        if self.file_type == "csv" {
-            self.headers = headers;
-            self.header_assigned = true;
-            self.write_headers().unwrap();
-            Ok(())
-        } else {
-            Err(HeaderError::FileTypeNotSupported(self.file_type.clone()))
+    /// Ends the current gzip member, syncs the file to disk and returns the
+    /// position reached.
+    pub fn state(&mut self) -> std::io::Result<DataWriterState> {
+        self.end_member()?;
+        self.current_file.sync_all()?;
+        Ok(DataWriterState {
+            master_path: self.master_path.clone(),
+            file_type: self.file_type.clone(),
+            file_number: self.file_number,
+            file_len: self.current_file.metadata()?.len(),
+            f_ln_written: self.f_ln_written,
+            t_ln_written: self.t_ln_written,
+            file_rows: self.file_rows.clone(),
+            header_written: self.header_written,
+            headers: self.headers.clone(),
+            header_assigned: self.header_assigned,
+        })
+    }
+
+    pub fn master_path(&self) -> &str {
+        &self.master_path
+    }
+
+    pub fn file_type(&self) -> &str {
+        &self.file_type
+    }
+
+    pub fn max_size_per_file(&self) -> u64 {
+        self.max_size_per_file
+    }
+
+    pub fn compression_level(&self) -> u32 {
+        self.compression_level
+    }
+
+    /// Starts a new data file once the current one reaches
+    /// `max_size_per_file` compressed bytes.
+    pub fn set_max_size_per_file(&mut self, max_size_per_file: u64) {
+        self.max_size_per_file = max_size_per_file;
+    }
+
+    /// Sets the gzip level (0-9) used for the data files.
+    pub fn set_compression_level(&mut self, compression_level: u32) {
+        self.compression_level = compression_level.min(9);
+    }
+
+    /// Moves every file written by `other` into this writer's directory,
+    /// numbered after the files already here, and removes `other`'s directory.
+    pub fn append_files_from(&mut self, other: &mut DataWriter) -> std::io::Result<()> {
+        other.close_current_file()?;
+        for file_number in 0..=other.file_number {
+            // An empty current file is replaced rather than kept in the archive
+            if self.current_file.metadata()?.len() > 0 {
+                self.close_current_file()?;
+                self.file_number += 1;
+                self.file_rows.push(0);
+            }
+            let path = self.file_path(self.file_number);
+            rename(other.file_path(file_number), &path)?;
+            self.current_file = OpenOptions::new().append(true).open(&path)?;
+            *self.file_rows.last_mut().unwrap() = other.file_rows[file_number as usize];
         }
+        self.f_ln_written = other.f_ln_written;
+        self.t_ln_written += other.t_ln_written;
+        self.headers = other.headers.clone();
+        self.header_written = other.header_written;
+        self.header_assigned = other.header_assigned;
+        remove_dir(&other.master_path)
+    }
+
+    pub fn assign_headers(&mut self, headers: Vec<String>) -> Result<(), DataWriterError> {
+        self.headers = headers;
+        self.header_assigned = true;
+        self.write_headers()
     }
 
     pub fn create_output_dir(base_folder_path: Option<&str>) -> std::io::Result<String> {
@@ -111,8 +288,8 @@ impl DataWriter {
         let mut folder_path;
         let mut result;
 
-        if base_folder_path.is_some() {
-            create_dir_all(base_folder_path.unwrap())?;
+        if let Some(base_folder_path) = base_folder_path {
+            create_dir_all(base_folder_path)?;
         }
 
         loop {
@@ -124,7 +301,7 @@ impl DataWriter {
             result = create_dir(&folder_path);
             match result {
                 Ok(_) => {
-                    return Ok(format!("./{}", folder_path));
+                    return Ok(folder_path);
                 }
                 Err(error) => {
                     if error.kind() == std::io::ErrorKind::AlreadyExists {
@@ -150,7 +327,12 @@ impl DataWriter {
         data: Vec<String>,
         add_new_line: Option<bool>,
     ) -> Result<(), DataWriterError> {
-        self.check_if_file_is_full_and_update().unwrap();
+        if self.file_type != "csv" {
+            return Err(DataWriterError::FileTypeNotSupported(
+                self.file_type.clone(),
+            ));
+        }
+        self.check_if_file_is_full_and_update()?;
         let mut data_string = String::new();
         for line in data.iter() {
             data_string.push_str(line);
@@ -158,99 +340,165 @@ impl DataWriter {
         }
         if add_new_line.unwrap_or(true) {
             data_string.push('\n');
-            self.f_ln_written += 1;
-            self.t_ln_written += 1;
+            self.count_row();
         }
-        self.current_file.write_all(data_string.as_bytes()).unwrap();
+        self.encoder()?.write_all(data_string.as_bytes())?;
+
+        Ok(())
+    }
 
+    /// Writes one row in the writer's output format.
+    pub fn write_integers(&mut self, row: &[&Integer]) -> Result<(), DataWriterError> {
+        self.check_if_file_is_full_and_update()?;
+        let bytes = self.format.encode_row(&self.headers, row);
+        self.encoder()?.write_all(&bytes)?;
+        self.count_row();
         Ok(())
     }
 
     pub fn close_and_compress_output(&mut self) -> Result<(), DataWriterError> {
-        self.close_current_file().unwrap();
-        let archive_path: String;
-
-        // TODO: Implement conversion form std error to data writer error
-        if self.archive_info.is_some() {
-            archive_path = format!(
-                "pi_{}_{}.tar.gz",
-                self.archive_info.as_ref().unwrap().batch_id,
-                self.archive_info.as_ref().unwrap().id,
-            );
-        } else {
-            archive_path = format!("archive.tar.gz");
+        self.close_current_file()?;
+        self.compress(None)
+    }
+
+    /// Like `close_and_compress_output`, storing a manifest of the data files
+    /// for terms `n_start..n_end` as the first entry of the archive.
+    pub fn close_and_compress_with_manifest(
+        &mut self,
+        n_start: i128,
+        n_end: i128,
+        compute: Duration,
+    ) -> Result<Manifest, DataWriterError> {
+        self.close_current_file()?;
+        let mut manifest = Manifest::new(n_start, n_end, &self.file_type, compute);
+        if let Some(archive_info) = &self.archive_info {
+            manifest.job_id = Some(archive_info.id);
+            manifest.batch_id = Some(archive_info.batch_id);
         }
-        let tar_gz = File::create(archive_path).unwrap();
-        let enc = GzEncoder::new(tar_gz, flate2::Compression::best());
+        for (file_number, rows) in self.file_rows.iter().enumerate() {
+            manifest.add_file(Path::new(&self.file_path(file_number as i32)), *rows)?;
+        }
+        self.compress(Some(&manifest))?;
+        Ok(manifest)
+    }
+
+    fn compress(&self, manifest: Option<&Manifest>) -> Result<(), DataWriterError> {
+        let tar_gz = File::create(self.archive_path())?;
+        // The data files are already compressed, so the archive only bundles them
+        let enc = GzEncoder::new(tar_gz, Compression::none());
         let mut tar = Builder::new(enc);
-        // TODO: Implement conversion form std error to data writer error
-        tar.append_dir_all("{}", &self.master_path).unwrap();
-        tar.finish().unwrap();
+        if let Some(manifest) = manifest {
+            let json = serde_json::to_vec_pretty(manifest).map_err(std::io::Error::from)?;
+            let mut header = Header::new_gnu();
+            header.set_size(json.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs(),
+            );
+            tar.append_data(
+                &mut header,
+                format!("{{}}/{}", MANIFEST_NAME),
+                json.as_slice(),
+            )?;
+        }
+        tar.append_dir_all("{}", &self.master_path)?;
+        tar.into_inner()?.finish()?.sync_all()?;
         Ok(())
     }
 
-    pub fn set_archive_id(&mut self, id: i32, batch_id: i32) {
+    /// Where `close_and_compress_output` writes the archive.
+    pub fn archive_path(&self) -> String {
+        match &self.archive_info {
+            Some(archive_info) => {
+                format!("pi_{}_{}.tar.gz", archive_info.batch_id, archive_info.id)
+            }
+            None => "archive.tar.gz".to_string(),
+        }
+    }
+
+    pub fn set_archive_id(&mut self, id: u64, batch_id: u64) {
         self.archive_info = Some(ArchiveInfo { id, batch_id });
     }
 
     fn get_next_file(&mut self) -> Result<(), DataWriterError> {
-        self.close_current_file().unwrap();
+        self.close_current_file()?;
 
         self.file_number += 1;
-        let _current_file_path = format!(
-            "{}/data{}.{}",
-            self.master_path, self.file_number, self.file_type
-        );
+        let _current_file_path = self.file_path(self.file_number);
         if Path::new(&_current_file_path).exists() {
             return Err(DataWriterError::FileAlreadyExists(_current_file_path));
         }
-        self.current_file = File::create(&_current_file_path).unwrap();
+        self.current_file = File::create(&_current_file_path)?;
         self.header_written = false;
         self.f_ln_written = 0;
+        self.file_rows.push(0);
 
         Ok(())
     }
 
-    fn check_if_file_is_full_and_update(&mut self) -> Result<(), HeaderError> {
-        if self.current_file.metadata().unwrap().len() >= self.max_size_per_file {
-            self.get_next_file().unwrap();
+    fn check_if_file_is_full_and_update(&mut self) -> Result<(), DataWriterError> {
+        if self.current_file.metadata()?.len() >= self.max_size_per_file {
+            self.get_next_file()?;
             if self.header_assigned {
-                self.write_headers().unwrap();
+                self.write_headers()?;
             }
         }
         Ok(())
     }
 
-    fn write_headers(&mut self) -> Result<(), HeaderError> {
+    fn write_headers(&mut self) -> Result<(), DataWriterError> {
         if self.header_written {
-            return Err(HeaderError::HeaderAlreadyWritten(self.headers.clone()));
+            return Err(HeaderError::HeaderAlreadyWritten(self.headers.clone()).into());
         };
-        if self.file_type == "csv" {
-            let mut header_string = String::new();
-            for header in self.headers.iter() {
-                header_string.push_str(header);
-                header_string.push(',');
-            }
-            header_string.push('\n');
-            if self.f_ln_written == 0 {
-                self.current_file
-                    .write_all(header_string.as_bytes())
-                    .unwrap();
-                self.header_written = true;
-                self.t_ln_written += 1;
-                self.f_ln_written += 1;
-                Ok(())
-            } else {
-                Err(HeaderError::TooLateToAddHeader(self.f_ln_written))
-            }
+        if self.f_ln_written == 0 {
+            let header_bytes = self.format.encode_header(&self.headers);
+            self.encoder()?.write_all(&header_bytes)?;
+            self.header_written = true;
+            self.t_ln_written += 1;
+            self.f_ln_written += 1;
+            Ok(())
         } else {
-            Err(HeaderError::FileTypeNotSupported(self.file_type.clone()))
+            Err(HeaderError::TooLateToAddHeader(self.f_ln_written).into())
+        }
+    }
+
+    fn file_path(&self, file_number: i32) -> String {
+        format!(
+            "{}/data{}.{}.gz",
+            self.master_path, file_number, self.file_type
+        )
+    }
+
+    fn count_row(&mut self) {
+        self.f_ln_written += 1;
+        self.t_ln_written += 1;
+        *self.file_rows.last_mut().unwrap() += 1;
+    }
+
+    fn encoder(&mut self) -> std::io::Result<&mut GzEncoder<File>> {
+        if self.encoder.is_none() {
+            let file = self.current_file.try_clone()?;
+            let level = Compression::new(self.compression_level);
+            self.encoder = Some(GzEncoder::new(file, level));
+        }
+        Ok(self.encoder.as_mut().unwrap())
+    }
+
+    /// Completes the gzip member being written, if any, so the file ends in a
+    /// whole gzip stream.
+    fn end_member(&mut self) -> std::io::Result<()> {
+        match self.encoder.take() {
+            Some(encoder) => encoder.finish().map(|_| ()),
+            None => Ok(()),
         }
     }
 
     fn close_current_file(&mut self) -> Result<(), DataWriterError> {
-        self.current_file.flush().unwrap();
-        self.current_file.sync_all().unwrap();
+        self.end_member()?;
+        self.current_file.sync_all()?;
         Ok(())
     }
 }
@@ -258,17 +506,19 @@ impl DataWriter {
 #[cfg(test)]
 mod test {
     use super::*;
+    use flate2::read::MultiGzDecoder;
+    use std::io::Read;
 
     #[test]
     fn test_header_error() {
-        let mut writer = DataWriter::new("csv", Some("./testing/data_writer"));
+        let mut writer = DataWriter::new("csv", Some("./testing/data_writer")).unwrap();
         let headers = vec![String::from("a"), String::from("b"), String::from("c")];
         writer.assign_headers(headers).unwrap();
     }
 
     #[test]
     fn test_file_writer() {
-        let mut writer = DataWriter::new("csv", Some("./testing/data_writer"));
+        let mut writer = DataWriter::new("csv", Some("./testing/data_writer")).unwrap();
         let headers = vec![String::from("a"), String::from("b"), String::from("c")];
         writer.assign_headers(headers).unwrap();
         let data = vec![String::from("1"), String::from("2"), String::from("3")];
@@ -282,9 +532,76 @@ mod test {
         // assert_eq!(writer.f_ln_written, 3);
     }
 
+    #[test]
+    fn test_resume_truncates_to_state() {
+        let mut writer = DataWriter::new("csv", Some("./testing/data_writer")).unwrap();
+        writer
+            .assign_headers(vec![String::from("a"), String::from("b")])
+            .unwrap();
+        writer
+            .write_data_using_array(vec![String::from("1"), String::from("2")], None)
+            .unwrap();
+        let state = writer.state().unwrap();
+        writer
+            .write_data_using_array(vec![String::from("3"), String::from("4")], None)
+            .unwrap();
+        // Leaves a whole gzip member after the state, as if the job stopped later on
+        drop(writer);
+
+        let mut resumed = DataWriter::resume(&state).unwrap();
+        resumed
+            .write_data_using_array(vec![String::from("5"), String::from("6")], None)
+            .unwrap();
+        resumed.state().unwrap();
+        let mut contents = String::new();
+        MultiGzDecoder::new(File::open(state.current_file_path()).unwrap())
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "a,b,\n1,2,\n5,6,\n");
+    }
+
+    #[test]
+    fn test_absolute_output_dir() {
+        let base = std::env::current_dir()
+            .unwrap()
+            .join("testing/data_writer_absolute");
+        let mut writer = DataWriter::new("csv", base.to_str()).unwrap();
+        assert!(Path::new(writer.master_path()).starts_with(&base));
+        writer.assign_headers(vec![String::from("a")]).unwrap();
+        writer
+            .write_data_using_array(vec![String::from("1")], None)
+            .unwrap();
+        let state = writer.state().unwrap();
+        assert!(Path::new(&state.current_file_path()).is_file());
+    }
+
+    #[test]
+    fn test_write_integers_in_each_format() {
+        let row = [&Integer::from(2), &Integer::from(-3)];
+        for file_type in ["csv", "jsonl", "bin"] {
+            let mut writer = DataWriter::new(file_type, Some("./testing/data_writer")).unwrap();
+            writer
+                .assign_headers(vec![String::from("a"), String::from("b")])
+                .unwrap();
+            writer.write_integers(&row).unwrap();
+            let state = writer.state().unwrap();
+
+            let mut decoded = vec![];
+            let mut file = MultiGzDecoder::new(File::open(state.current_file_path()).unwrap());
+            output_format_from_name(file_type)
+                .unwrap()
+                .decode_rows(&mut file, &mut |r| {
+                    decoded.push(r);
+                    Ok(())
+                })
+                .unwrap();
+            assert_eq!(decoded, vec![vec![Integer::from(2), Integer::from(-3)]]);
+        }
+    }
+
     #[test]
     fn test_compress_function() {
-        let mut writer = DataWriter::new("csv", Some("./testing/data_writer"));
+        let mut writer = DataWriter::new("csv", Some("./testing/data_writer")).unwrap();
         let headers = vec![String::from("a"), String::from("b"), String::from("c")];
         writer.assign_headers(headers).unwrap();
         let data = vec![String::from("1"), String::from("2"), String::from("3")];
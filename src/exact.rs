@@ -0,0 +1,147 @@
+//! Serde helpers for the integers of the job protocol, which have to get
+//! through JSON exactly, used with `#[serde(with = "crate::exact")]`.
+//!
+//! Values are written as JSON integers while any JSON parser can read them
+//! back exactly, and as decimal strings beyond that. Integers, decimal
+//! strings and, for workers and servers that still send floats, whole-valued
+//! floats up to 2^53 are read; anything else is rejected rather than rounded.
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{self, Visitor};
+use serde::{Deserializer, Serializer};
+
+/// 2^53, up to which every integer is exactly representable as an `f64`.
+const MAX_SAFE_INTEGER: i128 = 9_007_199_254_740_992;
+
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Copy + Into<i128>,
+    S: Serializer,
+{
+    let value: i128 = (*value).into();
+    if value.abs() <= MAX_SAFE_INTEGER {
+        serializer.serialize_i64(value as i64)
+    } else {
+        serializer.collect_str(&value)
+    }
+}
+
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: TryFrom<i128>,
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_any(ExactVisitor(PhantomData))
+}
+
+struct ExactVisitor<T>(PhantomData<T>);
+
+impl<T: TryFrom<i128>> ExactVisitor<T> {
+    fn convert<E: de::Error>(value: i128) -> Result<T, E> {
+        T::try_from(value).map_err(|_| E::custom(format!("{} is out of range", value)))
+    }
+}
+
+impl<T: TryFrom<i128>> Visitor<'_> for ExactVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an integer, or a decimal string of one")
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<T, E> {
+        Self::convert(value as i128)
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<T, E> {
+        Self::convert(value as i128)
+    }
+
+    fn visit_i128<E: de::Error>(self, value: i128) -> Result<T, E> {
+        Self::convert(value)
+    }
+
+    fn visit_u128<E: de::Error>(self, value: u128) -> Result<T, E> {
+        let value =
+            i128::try_from(value).map_err(|_| E::custom(format!("{} is out of range", value)))?;
+        Self::convert(value)
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<T, E> {
+        if value.fract() != 0.0 || value.abs() > MAX_SAFE_INTEGER as f64 {
+            return Err(E::custom(format!(
+                "{} is not exactly representable as an integer",
+                value
+            )));
+        }
+        Self::convert(value as i128)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<T, E> {
+        let parsed = value
+            .parse::<i128>()
+            .map_err(|_| E::custom(format!("{:?} is not a decimal integer", value)))?;
+        Self::convert(parsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Range {
+        #[serde(with = "crate::exact")]
+        id: u64,
+        #[serde(with = "crate::exact")]
+        n: i128,
+    }
+
+    fn parse(json: &str) -> Result<Range, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    #[test]
+    fn test_reads_exact_values() {
+        let expected = Range {
+            id: 300,
+            n: 16_777_217,
+        };
+        assert_eq!(parse(r#"{"id": 300, "n": 16777217}"#).unwrap(), expected);
+        assert_eq!(
+            parse(r#"{"id": 300.0, "n": 16777217.0}"#).unwrap(),
+            expected
+        );
+        assert_eq!(
+            parse(r#"{"id": "300", "n": "16777217"}"#).unwrap(),
+            expected
+        );
+        assert_eq!(
+            parse(r#"{"id": 1, "n": "100000000000000000000000"}"#)
+                .unwrap()
+                .n,
+            100_000_000_000_000_000_000_000
+        );
+    }
+
+    #[test]
+    fn test_rejects_inexact_values() {
+        assert!(parse(r#"{"id": 1.5, "n": 0}"#).is_err());
+        assert!(parse(r#"{"id": -1, "n": 0}"#).is_err());
+        assert!(parse(r#"{"id": 1, "n": 1e20}"#).is_err());
+        assert!(parse(r#"{"id": 1, "n": "1e3"}"#).is_err());
+    }
+
+    #[test]
+    fn test_writes_large_values_as_strings() {
+        let range = Range {
+            id: 7,
+            n: 100_000_000_000_000_000_000,
+        };
+        let json = serde_json::to_string(&range).unwrap();
+        assert_eq!(json, r#"{"id":7,"n":"100000000000000000000"}"#);
+        assert_eq!(parse(&json).unwrap(), range);
+    }
+}
@@ -0,0 +1,162 @@
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Where a job is in its life on a worker.
+///
+/// On the wire each status is the integer the API has always used, so
+/// `Accepted` is 3 rather than 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    Offered,
+    Rejected,
+    Accepted,
+    Running,
+    Completed,
+    Failed,
+    /// Stopped on a signal after checkpointing, ready to be offered again.
+    Paused,
+    /// Taken back by the coordinator when its worker's lease ran out or was
+    /// revoked, ready to be offered again. Workers never set it.
+    Requeued,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum JobStatusError {
+    UnknownStatus(i8),
+    InvalidTransition(JobStatus, JobStatus),
+}
+
+impl fmt::Display for JobStatusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JobStatusError::UnknownStatus(_s) => write!(f, "{} is not a job status", _s),
+            JobStatusError::InvalidTransition(_from, _to) => {
+                write!(f, "A job cannot go from {} to {}", _from, _to)
+            }
+        }
+    }
+}
+
+impl fmt::Display for JobStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            JobStatus::Offered => "offered",
+            JobStatus::Rejected => "rejected",
+            JobStatus::Accepted => "accepted",
+            JobStatus::Running => "running",
+            JobStatus::Completed => "completed",
+            JobStatus::Failed => "failed",
+            JobStatus::Paused => "paused",
+            JobStatus::Requeued => "requeued",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl JobStatus {
+    pub fn code(self) -> i8 {
+        match self {
+            JobStatus::Offered => 0,
+            JobStatus::Rejected => 1,
+            JobStatus::Accepted => 3,
+            JobStatus::Running => 4,
+            JobStatus::Completed => 5,
+            JobStatus::Failed => 6,
+            JobStatus::Paused => 7,
+            JobStatus::Requeued => 8,
+        }
+    }
+
+    /// Offered → Rejected or Accepted → Running → Completed, Failed or Paused.
+    pub fn can_transition_to(self, next: JobStatus) -> bool {
+        matches!(
+            (self, next),
+            (JobStatus::Offered, JobStatus::Rejected)
+                | (JobStatus::Offered, JobStatus::Accepted)
+                | (JobStatus::Accepted, JobStatus::Running)
+                | (JobStatus::Running, JobStatus::Completed)
+                | (JobStatus::Running, JobStatus::Failed)
+                | (JobStatus::Running, JobStatus::Paused)
+        )
+    }
+
+    /// Returns `next` if a job in this status may move to it.
+    pub fn transition(self, next: JobStatus) -> Result<JobStatus, JobStatusError> {
+        if self.can_transition_to(next) {
+            Ok(next)
+        } else {
+            Err(JobStatusError::InvalidTransition(self, next))
+        }
+    }
+}
+
+impl TryFrom<i8> for JobStatus {
+    type Error = JobStatusError;
+
+    fn try_from(code: i8) -> Result<Self, Self::Error> {
+        match code {
+            0 => Ok(JobStatus::Offered),
+            1 => Ok(JobStatus::Rejected),
+            3 => Ok(JobStatus::Accepted),
+            4 => Ok(JobStatus::Running),
+            5 => Ok(JobStatus::Completed),
+            6 => Ok(JobStatus::Failed),
+            7 => Ok(JobStatus::Paused),
+            8 => Ok(JobStatus::Requeued),
+            _ => Err(JobStatusError::UnknownStatus(code)),
+        }
+    }
+}
+
+impl Serialize for JobStatus {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i8(self.code())
+    }
+}
+
+impl<'de> Deserialize<'de> for JobStatus {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = i8::deserialize(deserializer)?;
+        JobStatus::try_from(code).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wire_values() {
+        assert_eq!(
+            serde_json::to_string(&[JobStatus::Accepted, JobStatus::Failed]).unwrap(),
+            "[3,6]"
+        );
+        assert_eq!(
+            serde_json::from_str::<JobStatus>("5").unwrap(),
+            JobStatus::Completed
+        );
+        assert!(serde_json::from_str::<JobStatus>("2").is_err());
+    }
+
+    #[test]
+    fn test_transitions() {
+        let status = JobStatus::Offered
+            .transition(JobStatus::Accepted)
+            .and_then(|s| s.transition(JobStatus::Running))
+            .and_then(|s| s.transition(JobStatus::Failed));
+        assert_eq!(status, Ok(JobStatus::Failed));
+        assert_eq!(
+            JobStatus::Offered.transition(JobStatus::Completed),
+            Err(JobStatusError::InvalidTransition(
+                JobStatus::Offered,
+                JobStatus::Completed
+            ))
+        );
+        assert!(!JobStatus::Completed.can_transition_to(JobStatus::Running));
+        assert!(!JobStatus::Rejected.can_transition_to(JobStatus::Accepted));
+        assert!(JobStatus::Running.can_transition_to(JobStatus::Paused));
+        assert!(!JobStatus::Paused.can_transition_to(JobStatus::Completed));
+        assert!(!JobStatus::Running.can_transition_to(JobStatus::Requeued));
+    }
+}
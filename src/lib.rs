@@ -1,5 +1,33 @@
 pub mod status_handler;
 
+pub mod config;
+
+pub mod exact;
+
+pub mod retry;
+
+pub mod artifact_store;
+
+pub mod job_status;
+
 pub mod data_handler;
 
+pub mod manifest;
+
+pub mod output_format;
+
+pub mod checkpoint;
+
 pub mod pi_math;
+
+pub mod pi_digits;
+
+pub mod reducer;
+
+pub mod spot_check;
+
+pub mod scheduler;
+
+pub mod shutdown;
+
+pub mod coordinator;
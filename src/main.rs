@@ -1,14 +1,403 @@
-use calculating_pi_rust::status_handler::StatusHandler;
+use calculating_pi_rust::config::WorkerConfig;
+use calculating_pi_rust::coordinator::Coordinator;
+use calculating_pi_rust::pi_digits::{
+    pi_digits, pi_digits_from_scratch, verify_pi_digits, write_pi_digits,
+};
+use calculating_pi_rust::pi_math::{CalcPi, ChudnovskySum};
+use calculating_pi_rust::reducer::{
+    inspect_archive, read_archive, read_manifest, reduce_archives, reduce_directory, verify_archive,
+};
+use calculating_pi_rust::scheduler::LocalScheduler;
+use calculating_pi_rust::shutdown;
+use calculating_pi_rust::spot_check::spot_check_archive;
+use calculating_pi_rust::status_handler::{StatusHandler, StatusHandlerError};
+use clap::{Args, Parser, Subcommand};
 use std::env;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+
+/// Exit status of a worker that turned down `api.max_rejections` jobs it
+/// could not run, so a submit file can tell it apart from a failed job.
+const EXIT_NO_SUITABLE_JOB: i32 = 3;
+
+#[derive(Parser)]
+#[command(version, about = "Computes Chudnovsky series terms for pi")]
+struct Cli {
+    /// Config file to read instead of $PI_CONFIG or ./calculating_pi.toml
+    #[arg(long, global = true)]
+    config: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Take a job from the API and compute it
+    Worker(WorkerArgs),
+    /// Compute a range of terms locally, without the API
+    Compute(ComputeArgs),
+    /// Run a whole batch on this machine, without the API, and write the digits
+    Local(LocalArgs),
+    /// Serve the worker-nodes API from a local job queue
+    Serve(ServeArgs),
+    /// Merge every pi_*.tar.gz archive in a directory into one sum file
+    Merge {
+        archive_dir: String,
+        output_sum_file: String,
+    },
+    /// Write digits of pi computed from a sum file, or from scratch
+    Digits(DigitsArgs),
+    /// Check a digits file against an independently computed pi
+    Verify {
+        digits_file: String,
+        /// The digits are hexadecimal
+        #[arg(long)]
+        hex: bool,
+    },
+    /// Show the data files and term range of an archive, checking them against its manifest
+    Inspect { archive: PathBuf },
+    /// Recompute random rows of an archive and compare them with the stored values
+    SpotCheck {
+        archive: PathBuf,
+        #[arg(default_value_t = 20)]
+        samples: usize,
+        /// Seed for choosing the rows, to repeat a check
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+}
+
+#[derive(Args)]
+struct WorkerArgs {
+    /// Process id reported to the API
+    process_id: Option<i32>,
+    /// Cluster id reported to the API
+    #[arg(requires = "process_id")]
+    cluster_id: Option<i32>,
+}
+
+#[derive(Args)]
+struct ComputeArgs {
+    /// First term to compute
+    #[arg(long)]
+    start: u64,
+    /// One past the last term to compute
+    #[arg(long)]
+    end: u64,
+    #[arg(long, default_value = "csv", value_parser = ["csv", "jsonl", "bin"])]
+    format: String,
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    threads: u16,
+    /// Write one binary splitting triple per this many terms instead of every term
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    terms_per_triple: Option<u64>,
+    /// Batch id used in the archive name pi_{batch}_{job}.tar.gz
+    #[arg(long, default_value_t = 0)]
+    batch: u64,
+    /// Job id used in the archive name pi_{batch}_{job}.tar.gz
+    #[arg(long, default_value_t = 0)]
+    job: u64,
+}
+
+#[derive(Args)]
+struct LocalArgs {
+    /// Number of digits to compute
+    #[arg(long)]
+    digits: u32,
+    output_file: String,
+    #[arg(long, default_value_t = 100_000, value_parser = clap::value_parser!(u32).range(1..))]
+    terms_per_job: u32,
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    threads: u16,
+    /// Batch id used in the archive names pi_{batch}_{job}.tar.gz
+    #[arg(long, default_value_t = 0)]
+    batch: u64,
+    /// Write hexadecimal digits
+    #[arg(long)]
+    hex: bool,
+}
+
+#[derive(Args)]
+struct ServeArgs {
+    #[arg(long, default_value = "127.0.0.1:8000")]
+    listen: SocketAddr,
+    /// File the job queue is saved to and loaded from
+    #[arg(long, default_value = "coordinator.json")]
+    state: PathBuf,
+    /// Seconds a running job may go without a heartbeat before it is requeued
+    #[arg(long, default_value_t = 60)]
+    lease_secs: u64,
+    /// Add the jobs for this many digits to the queue
+    #[arg(long)]
+    digits: Option<u32>,
+    #[arg(long, default_value_t = 100_000, value_parser = clap::value_parser!(u32).range(1..))]
+    terms_per_job: u32,
+    /// Cores each queued job asks for
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    threads: u16,
+    /// Batch id of the queued jobs
+    #[arg(long, default_value_t = 0)]
+    batch: u64,
+}
+
+#[derive(Args)]
+struct DigitsArgs {
+    count: u32,
+    output_file: String,
+    /// Write hexadecimal digits
+    #[arg(long)]
+    hex: bool,
+    /// Sum file written by merge; without it the series is summed locally
+    #[arg(long)]
+    sum: Option<String>,
+}
 
 fn main() {
-    let mut sh = StatusHandler::new("https://piapi.oscorp.ml".to_string());
-    if env::args().len() > 1 {
-        sh.set_node_info(
-            env::args().nth(1).unwrap().parse::<i32>().unwrap(),
-            env::args().nth(2).unwrap().parse::<i32>().unwrap(),
+    let cli = Cli::parse_from(worker_args_compat(env::args().collect()));
+    shutdown::install();
+    match cli.command {
+        Command::Worker(args) => run_worker(load_config(cli.config), args),
+        Command::Compute(args) => run_compute(load_config(cli.config), args),
+        Command::Local(args) => run_local(load_config(cli.config), args),
+        Command::Serve(args) => run_serve(args),
+        Command::Merge {
+            archive_dir,
+            output_sum_file,
+        } => run_merge(&archive_dir, &output_sum_file),
+        Command::Digits(args) => run_digits(args),
+        Command::Verify { digits_file, hex } => run_verify(&digits_file, hex),
+        Command::Inspect { archive } => run_inspect(&archive),
+        Command::SpotCheck {
+            archive,
+            samples,
+            seed,
+        } => run_spot_check(&archive, samples, seed),
+    }
+}
+
+/// Submit files still run the binary bare or as `calculating_pi_rust $(Process) $(Cluster)`,
+/// so both mean `worker`.
+fn worker_args_compat(mut args: Vec<String>) -> Vec<String> {
+    let bare = args.len() == 1;
+    let positional_ids = args.get(1).is_some_and(|a| a.parse::<i32>().is_ok());
+    if bare || positional_ids {
+        args.insert(1, "worker".to_string());
+    }
+    args
+}
+
+fn load_config(path: Option<String>) -> WorkerConfig {
+    WorkerConfig::load(path.as_deref()).unwrap_or_else(|e| exit_with(&e.to_string()))
+}
+
+fn run_worker(config: WorkerConfig, args: WorkerArgs) {
+    let mut sh = StatusHandler::from_config(config);
+    if let (Some(process_id), Some(cluster_id)) = (args.process_id, args.cluster_id) {
+        sh.set_node_info(process_id, cluster_id);
+    }
+    match sh.get_job() {
+        Ok(()) => run_job(&mut sh),
+        Err(e @ StatusHandlerError::TooManyRejections(_)) => {
+            eprintln!("{}", e);
+            process::exit(EXIT_NO_SUITABLE_JOB);
+        }
+        Err(e) => exit_with(&e.to_string()),
+    }
+}
+
+/// Runs the job `sh` holds, telling the server if it fails before exiting non-zero.
+fn run_job(sh: &mut StatusHandler) {
+    if let Err(e) = sh.dispatch_job() {
+        if let Err(report_error) = sh.report_failure(&e) {
+            eprintln!("Could not report the failure: {}", report_error);
+        }
+        exit_with(&e.to_string());
+    }
+}
+
+fn run_compute(config: WorkerConfig, args: ComputeArgs) {
+    if args.end <= args.start {
+        exit_with("--end must be greater than --start");
+    }
+    let mut calc_pi = CalcPi::with_output_format(
+        args.start as i128,
+        args.end as i128,
+        Some(&config.output_dir),
+        &args.format,
+    )
+    .unwrap_or_else(|e| exit_with(&e.to_string()));
+    calc_pi.apply_config(&config);
+    calc_pi.set_threads(args.threads as usize);
+    if let Some(terms) = args.terms_per_triple {
+        calc_pi.set_terms_per_triple(terms as i128);
+    }
+    calc_pi.set_data_handler_archive_id(args.job, args.batch);
+    calc_pi
+        .calc_pi_terms()
+        .unwrap_or_else(|e| exit_with(&e.to_string()));
+    println!(
+        "Wrote terms {} to {} to pi_{}_{}.tar.gz",
+        args.start,
+        args.end - 1,
+        args.batch,
+        args.job
+    );
+}
+
+fn run_local(config: WorkerConfig, args: LocalArgs) {
+    let radix = if args.hex { 16 } else { 10 };
+    let scheduler = LocalScheduler::for_digits(
+        args.batch,
+        args.digits,
+        radix,
+        args.terms_per_job as i128,
+        args.threads as usize,
+    )
+    .unwrap_or_else(|e| exit_with(&e.to_string()));
+    let archive_names = scheduler.archive_names();
+    let mut sh = StatusHandler::with_local_scheduler(config, scheduler);
+    loop {
+        // A signal that arrived while the last job was being uploaded
+        if shutdown::requested() {
+            exit_with("Stopped on a signal before the next job");
+        }
+        match sh.get_job() {
+            Ok(()) => run_job(&mut sh),
+            Err(StatusHandlerError::NoJobsLeft) => break,
+            Err(e) => exit_with(&e.to_string()),
+        }
+    }
+
+    let sum = reduce_archives(&archive_names).unwrap_or_else(|e| exit_with(&e.to_string()));
+    let digit_string =
+        pi_digits(&sum, args.digits, radix).unwrap_or_else(|e| exit_with(&e.to_string()));
+    write_pi_digits(&digit_string, &args.output_file).unwrap_or_else(|e| exit_with(&e.to_string()));
+    println!(
+        "Wrote {} digits from {} jobs to {}",
+        args.digits,
+        archive_names.len(),
+        args.output_file
+    );
+}
+
+#[tokio::main]
+async fn run_serve(args: ServeArgs) {
+    let coordinator = Coordinator::open(&args.state)
+        .unwrap_or_else(|e| exit_with(&e.to_string()))
+        .with_lease_duration(Duration::from_secs(args.lease_secs));
+    if let Some(digits) = args.digits {
+        let mut scheduler = LocalScheduler::for_digits(
+            args.batch,
+            digits,
+            10,
+            args.terms_per_job as i128,
+            args.threads as usize,
+        )
+        .unwrap_or_else(|e| exit_with(&e.to_string()));
+        while let Some(job) = scheduler.next_job() {
+            coordinator
+                .enqueue(job)
+                .unwrap_or_else(|e| exit_with(&e.to_string()));
+        }
+    }
+    let listener = tokio::net::TcpListener::bind(args.listen)
+        .await
+        .unwrap_or_else(|e| exit_with(&e.to_string()));
+    println!(
+        "Serving {} jobs on http://{}",
+        coordinator.jobs().len(),
+        args.listen
+    );
+    coordinator
+        .serve(listener)
+        .await
+        .unwrap_or_else(|e| exit_with(&e.to_string()));
+}
+
+fn run_digits(args: DigitsArgs) {
+    let radix = if args.hex { 16 } else { 10 };
+    let result = match args.sum {
+        Some(sum_path) => {
+            let sum = ChudnovskySum::load(&sum_path).unwrap_or_else(|e| exit_with(&e.to_string()));
+            pi_digits(&sum, args.count, radix)
+        }
+        None => pi_digits_from_scratch(args.count, radix),
+    };
+    let digit_string = result.unwrap_or_else(|e| exit_with(&e.to_string()));
+    write_pi_digits(&digit_string, &args.output_file).unwrap_or_else(|e| exit_with(&e.to_string()));
+}
+
+fn run_merge(archive_dir: &str, output_sum_file: &str) {
+    let merged = reduce_directory(archive_dir).unwrap_or_else(|e| exit_with(&e.to_string()));
+    println!(
+        "Merged terms {} to {} into {}",
+        merged.n_start,
+        merged.n_end - 1,
+        output_sum_file
+    );
+    merged
+        .save(output_sum_file)
+        .unwrap_or_else(|e| exit_with(&e.to_string()));
+}
+
+fn run_verify(digits_file: &str, hex: bool) {
+    let radix = if hex { 16 } else { 10 };
+    let digit_string =
+        std::fs::read_to_string(digits_file).unwrap_or_else(|e| exit_with(&e.to_string()));
+    match verify_pi_digits(&digit_string, radix) {
+        Ok(count) => println!("All {} digits of {} are correct", count, digits_file),
+        Err(e) => exit_with(&e.to_string()),
+    }
+}
+
+fn run_inspect(archive: &Path) {
+    let manifest = read_manifest(archive).unwrap_or_else(|e| exit_with(&e.to_string()));
+    if manifest.is_some() {
+        let manifest = verify_archive(archive).unwrap_or_else(|e| exit_with(&e.to_string()));
+        if let (Some(job_id), Some(batch_id)) = (manifest.job_id, manifest.batch_id) {
+            println!("Job {} of batch {}", job_id, batch_id);
+        }
+        println!(
+            "Terms {} to {} in {} rows, computed in {:.1}s by version {}",
+            manifest.n_start,
+            manifest.n_end - 1,
+            manifest.rows(),
+            manifest.compute_secs,
+            manifest.crate_version
         );
+        println!("Every data file matches the manifest");
     }
-    sh.get_job().unwrap();
-    sh.dispatch_job();
+    let summaries = inspect_archive(archive).unwrap_or_else(|e| exit_with(&e.to_string()));
+    for summary in summaries.iter() {
+        let rows = if summary.triples { "triples" } else { "rows" };
+        match summary.n_range {
+            Some((first, last)) => println!(
+                "{}: {} {}, n={} to {}",
+                summary.file_name, summary.rows, rows, first, last
+            ),
+            None => println!("{}: no rows", summary.file_name),
+        }
+    }
+    let sum = read_archive(archive).unwrap_or_else(|e| exit_with(&e.to_string()));
+    println!("Terms {} to {} with no gaps", sum.n_start, sum.n_end - 1);
+}
+
+fn run_spot_check(archive: &Path, samples: usize, seed: Option<u64>) {
+    let report =
+        spot_check_archive(archive, samples, seed).unwrap_or_else(|e| exit_with(&e.to_string()));
+    println!("Checked rows n={:?}", report.checked);
+    if !report.mismatches.is_empty() {
+        for (n, columns) in report.mismatches.iter() {
+            eprintln!("Row n={} disagrees in {}", n, columns.join(", "));
+        }
+        process::exit(1);
+    }
+}
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
 }
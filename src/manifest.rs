@@ -0,0 +1,69 @@
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::artifact_store::sha256_file;
+
+/// Name of the manifest entry, stored first in every archive under `{}/`.
+pub const MANIFEST_NAME: &str = "manifest.json";
+
+/// Describes what an archive holds, so it can be checked without decoding
+/// every data file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub job_id: Option<u64>,
+    pub batch_id: Option<u64>,
+    /// The archive holds terms `n_start..n_end`.
+    pub n_start: i128,
+    pub n_end: i128,
+    pub file_type: String,
+    /// The data files in file number order.
+    pub files: Vec<ManifestFile>,
+    pub crate_version: String,
+    /// Time spent computing the terms, including runs before a resume.
+    pub compute_secs: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ManifestFile {
+    pub name: String,
+    /// Term rows in the file, not counting the header.
+    pub rows: u64,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl Manifest {
+    pub fn new(n_start: i128, n_end: i128, file_type: &str, compute: Duration) -> Self {
+        Manifest {
+            job_id: None,
+            batch_id: None,
+            n_start,
+            n_end,
+            file_type: file_type.to_string(),
+            files: vec![],
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            compute_secs: compute.as_secs_f64(),
+        }
+    }
+
+    /// Hashes the data file at `path` and adds it to the manifest.
+    pub fn add_file(&mut self, path: &Path, rows: u64) -> std::io::Result<()> {
+        self.files.push(ManifestFile {
+            name: path.file_name().unwrap().to_string_lossy().to_string(),
+            rows,
+            size: path.metadata()?.len(),
+            sha256: sha256_file(path)?,
+        });
+        Ok(())
+    }
+
+    pub fn rows(&self) -> u64 {
+        self.files.iter().map(|file| file.rows).sum()
+    }
+
+    pub fn file(&self, name: &str) -> Option<&ManifestFile> {
+        self.files.iter().find(|file| file.name == name)
+    }
+}
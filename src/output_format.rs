@@ -0,0 +1,351 @@
+use std::io::{BufRead, BufReader, Read};
+
+use rug::integer::Order;
+use rug::Integer;
+
+/// Magic bytes at the start of every binary limb file.
+const LIMB_MAGIC: &[u8; 8] = b"PILIMBS1";
+
+/// How `DataWriter` encodes rows of integers and how readers decode them again.
+///
+/// Every format starts a file with the column names, and decoded rows come
+/// back in that column order.
+pub trait OutputFormat: Send {
+    /// Name of the format, also used as the data file extension.
+    fn file_type(&self) -> &'static str;
+
+    fn encode_header(&self, headers: &[String]) -> Vec<u8>;
+
+    fn encode_row(&self, headers: &[String], row: &[&Integer]) -> Vec<u8>;
+
+    /// Calls `f` with every row stored in `reader`.
+    fn decode_rows(
+        &self,
+        reader: &mut dyn Read,
+        f: &mut dyn FnMut(Vec<Integer>) -> Result<(), String>,
+    ) -> Result<(), String>;
+}
+
+pub fn output_format_from_name(file_type: &str) -> Option<Box<dyn OutputFormat>> {
+    match file_type {
+        "csv" => Some(Box::new(Csv)),
+        "jsonl" => Some(Box::new(JsonLines)),
+        "bin" => Some(Box::new(BinaryLimbs)),
+        _ => None,
+    }
+}
+
+/// Decimal values separated by commas, one row per line.
+pub struct Csv;
+
+/// One JSON object per line with decimal string values, after a first line
+/// listing the columns.
+pub struct JsonLines;
+
+/// GMP limbs exported as little-endian u64 words, which is much faster to
+/// write and read back than decimal for large values.
+///
+/// After the header each value is a sign byte (1 for negative), the number of
+/// limbs as a u64 and then the limbs, least significant first.
+pub struct BinaryLimbs;
+
+impl OutputFormat for Csv {
+    fn file_type(&self) -> &'static str {
+        "csv"
+    }
+
+    fn encode_header(&self, headers: &[String]) -> Vec<u8> {
+        let mut header_string = String::new();
+        for header in headers.iter() {
+            header_string.push_str(header);
+            header_string.push(',');
+        }
+        header_string.push('\n');
+        header_string.into_bytes()
+    }
+
+    fn encode_row(&self, _headers: &[String], row: &[&Integer]) -> Vec<u8> {
+        let mut data_string = String::new();
+        for value in row.iter() {
+            data_string.push_str(&value.to_string());
+            data_string.push(',');
+        }
+        data_string.push('\n');
+        data_string.into_bytes()
+    }
+
+    fn decode_rows(
+        &self,
+        reader: &mut dyn Read,
+        f: &mut dyn FnMut(Vec<Integer>) -> Result<(), String>,
+    ) -> Result<(), String> {
+        for (line_number, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line.map_err(|e| e.to_string())?;
+            if line.is_empty() || (line_number == 0 && !line.starts_with(is_number_start)) {
+                continue;
+            }
+            let row = line
+                .trim_end_matches(',')
+                .split(',')
+                .map(|value| parse_decimal(value, line_number))
+                .collect::<Result<Vec<Integer>, String>>()?;
+            f(row)?;
+        }
+        Ok(())
+    }
+}
+
+impl OutputFormat for JsonLines {
+    fn file_type(&self) -> &'static str {
+        "jsonl"
+    }
+
+    fn encode_header(&self, headers: &[String]) -> Vec<u8> {
+        let mut line = serde_json::json!({ "columns": headers }).to_string();
+        line.push('\n');
+        line.into_bytes()
+    }
+
+    fn encode_row(&self, headers: &[String], row: &[&Integer]) -> Vec<u8> {
+        let object: serde_json::Map<String, serde_json::Value> = headers
+            .iter()
+            .zip(row.iter())
+            .map(|(header, value)| (header.clone(), value.to_string().into()))
+            .collect();
+        let mut line = serde_json::Value::Object(object).to_string();
+        line.push('\n');
+        line.into_bytes()
+    }
+
+    fn decode_rows(
+        &self,
+        reader: &mut dyn Read,
+        f: &mut dyn FnMut(Vec<Integer>) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let mut columns: Option<Vec<String>> = None;
+        for (line_number, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line.map_err(|e| e.to_string())?;
+            if line.is_empty() {
+                continue;
+            }
+            let value: serde_json::Value = serde_json::from_str(&line)
+                .map_err(|e| format!("line {}: {}", line_number + 1, e))?;
+            let names = match &columns {
+                Some(names) => names,
+                None => {
+                    columns = Some(
+                        serde_json::from_value(value["columns"].clone())
+                            .map_err(|_| "the first line does not list the columns")?,
+                    );
+                    continue;
+                }
+            };
+            let row = names
+                .iter()
+                .map(|name| match value[name].as_str() {
+                    Some(s) => parse_decimal(s, line_number),
+                    None => Err(format!("line {} has no {}", line_number + 1, name)),
+                })
+                .collect::<Result<Vec<Integer>, String>>()?;
+            f(row)?;
+        }
+        Ok(())
+    }
+}
+
+impl OutputFormat for BinaryLimbs {
+    fn file_type(&self) -> &'static str {
+        "bin"
+    }
+
+    fn encode_header(&self, headers: &[String]) -> Vec<u8> {
+        let mut bytes = LIMB_MAGIC.to_vec();
+        bytes.extend_from_slice(&(headers.len() as u32).to_le_bytes());
+        for header in headers.iter() {
+            bytes.extend_from_slice(&(header.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(header.as_bytes());
+        }
+        bytes
+    }
+
+    fn encode_row(&self, _headers: &[String], row: &[&Integer]) -> Vec<u8> {
+        let mut bytes = vec![];
+        for value in row.iter() {
+            let limbs = value.to_digits::<u64>(Order::Lsf);
+            bytes.push((*value < &0) as u8);
+            bytes.extend_from_slice(&(limbs.len() as u64).to_le_bytes());
+            for limb in limbs.iter() {
+                bytes.extend_from_slice(&limb.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    fn decode_rows(
+        &self,
+        reader: &mut dyn Read,
+        f: &mut dyn FnMut(Vec<Integer>) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let mut reader = BufReader::new(reader);
+        let mut magic = vec![];
+        (&mut reader)
+            .take(LIMB_MAGIC.len() as u64)
+            .read_to_end(&mut magic)
+            .map_err(|e| e.to_string())?;
+        match magic.as_slice() {
+            // An empty file has no rows
+            [] => return Ok(()),
+            magic if magic == LIMB_MAGIC => {}
+            _ => return Err("the file does not start with the limb header".to_string()),
+        }
+        // Counts come from the file, so buffers only grow as the bytes arrive
+        let column_count = read_u32(&mut reader)?;
+        // Rows with no columns would take no bytes, so there would be no end to them
+        if column_count == 0 {
+            return Err("the header has no columns".to_string());
+        }
+        for _ in 0..column_count {
+            let name_len = read_u32(&mut reader)?;
+            read_bytes(&mut reader, name_len as u64)?;
+        }
+
+        loop {
+            let mut row = vec![];
+            for column in 0..column_count {
+                let mut sign = [0_u8; 1];
+                match reader.read(&mut sign).map_err(|e| e.to_string())? {
+                    0 if column == 0 => return Ok(()),
+                    0 => return Err("the file ends in the middle of a row".to_string()),
+                    _ => {}
+                }
+                let limb_count = read_u64(&mut reader)?;
+                let byte_count = limb_count
+                    .checked_mul(8)
+                    .ok_or_else(|| format!("{} limbs is too many", limb_count))?;
+                // Little-endian limbs, least significant first, are little-endian bytes
+                let value = Integer::from_digits(&read_bytes(&mut reader, byte_count)?, Order::Lsf);
+                row.push(if sign[0] == 1 { -value } else { value });
+            }
+            f(row)?;
+        }
+    }
+}
+
+fn is_number_start(c: char) -> bool {
+    c.is_ascii_digit() || c == '-'
+}
+
+fn parse_decimal(value: &str, line_number: usize) -> Result<Integer, String> {
+    Integer::from_str_radix(value, 10)
+        .map_err(|_| format!("line {} has an invalid value {}", line_number + 1, value))
+}
+
+/// Reads exactly `len` bytes, without trusting `len` enough to allocate it up front.
+fn read_bytes(reader: &mut impl Read, len: u64) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![];
+    reader
+        .take(len)
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+    if (bytes.len() as u64) < len {
+        return Err("the file ends part way through a value".to_string());
+    }
+    Ok(bytes)
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, String> {
+    let mut bytes = [0_u8; 4];
+    reader.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64, String> {
+    let mut bytes = [0_u8; 8];
+    reader.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(format: &dyn OutputFormat) {
+        let headers = vec!["n".to_string(), "x".to_string()];
+        let rows = vec![
+            vec![Integer::from(0), Integer::from(1)],
+            vec![
+                Integer::from(7),
+                Integer::from(-262537412640768000_i64) * Integer::from(u128::MAX),
+            ],
+        ];
+        let mut bytes = format.encode_header(&headers);
+        for row in rows.iter() {
+            let refs: Vec<&Integer> = row.iter().collect();
+            bytes.extend(format.encode_row(&headers, &refs));
+        }
+
+        let mut decoded = vec![];
+        format
+            .decode_rows(&mut bytes.as_slice(), &mut |row| {
+                decoded.push(row);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(decoded, rows);
+    }
+
+    #[test]
+    fn test_formats_round_trip() {
+        for file_type in ["csv", "jsonl", "bin"] {
+            round_trip(output_format_from_name(file_type).unwrap().as_ref());
+        }
+        assert!(output_format_from_name("parquet").is_none());
+    }
+
+    #[test]
+    fn test_huge_binary_counts() {
+        let headers = vec!["n".to_string()];
+        let mut bytes = BinaryLimbs.encode_header(&headers);
+        bytes.push(0);
+        bytes.extend_from_slice(&(u64::MAX / 8).to_le_bytes());
+        bytes.extend_from_slice(&[0; 16]);
+        let result = BinaryLimbs.decode_rows(&mut bytes.as_slice(), &mut |_| Ok(()));
+        assert_eq!(
+            result,
+            Err("the file ends part way through a value".to_string())
+        );
+
+        let mut bytes = LIMB_MAGIC.to_vec();
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        let result = BinaryLimbs.decode_rows(&mut bytes.as_slice(), &mut |_| Ok(()));
+        assert_eq!(
+            result,
+            Err("the file ends part way through a value".to_string())
+        );
+    }
+
+    #[test]
+    fn test_invalid_binary_header() {
+        let decode = |bytes: &[u8]| BinaryLimbs.decode_rows(&mut &bytes[..], &mut |_| Ok(()));
+        assert_eq!(decode(&[]), Ok(()));
+        assert_eq!(
+            decode(&LIMB_MAGIC[..3]),
+            Err("the file does not start with the limb header".to_string())
+        );
+        assert_eq!(
+            decode(&BinaryLimbs.encode_header(&[])),
+            Err("the header has no columns".to_string())
+        );
+    }
+
+    #[test]
+    fn test_truncated_binary_row() {
+        let headers = vec!["n".to_string(), "x".to_string()];
+        let mut bytes = BinaryLimbs.encode_header(&headers);
+        bytes.extend(BinaryLimbs.encode_row(&headers, &[&Integer::from(3), &Integer::from(4)]));
+        bytes.truncate(bytes.len() - 3);
+        let result = BinaryLimbs.decode_rows(&mut bytes.as_slice(), &mut |_| Ok(()));
+        assert!(result.is_err());
+    }
+}
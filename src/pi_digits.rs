@@ -0,0 +1,235 @@
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+
+use rug::float::{Constant, Round};
+use rug::ops::Pow;
+use rug::{Float, Integer, Rational};
+
+use crate::pi_math::{BinarySplit, ChudnovskySum};
+
+/// Decimal digits of pi contributed by each term of the Chudnovsky series.
+const DIGITS_PER_TERM: f64 = 14.181647462725477;
+
+/// Extra digits carried through the float computation so the truncated
+/// output is not affected by rounding in the last places.
+const GUARD_DIGITS: u32 = 16;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PiDigitsError {
+    UnsupportedRadix(i32),
+    SumDoesNotStartAtZero(i128),
+    NotEnoughTerms(i128, i128),
+    WriteError(String),
+    InvalidDigits(String),
+    DigitMismatch(usize, char, char),
+    TooManyDigits(u32),
+}
+
+impl fmt::Display for PiDigitsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PiDigitsError::UnsupportedRadix(_r) => {
+                write!(f, "Radix {} is not supported, use 10 or 16", _r)
+            }
+            PiDigitsError::SumDoesNotStartAtZero(_n) => {
+                write!(f, "The sum starts at n={}, it must start at n=0", _n)
+            }
+            PiDigitsError::NotEnoughTerms(_have, _need) => write!(
+                f,
+                "The sum only has {} terms, {} are needed for the requested digits",
+                _have, _need
+            ),
+            PiDigitsError::WriteError(_s) => write!(f, "Could not write digits: {}", _s),
+            PiDigitsError::InvalidDigits(_s) => write!(f, "The digits are not valid: {}", _s),
+            PiDigitsError::DigitMismatch(_p, _e, _f) => write!(
+                f,
+                "Digit {} after the point is {}, it should be {}",
+                _p, _f, _e
+            ),
+            PiDigitsError::TooManyDigits(_d) => {
+                write!(f, "{} digits need more precision than MPFR supports", _d)
+            }
+        }
+    }
+}
+
+/// Number of series terms needed for `digits` correct digits in `radix`.
+pub fn terms_needed(digits: u32, radix: i32) -> Result<i128, PiDigitsError> {
+    Ok((decimal_digits(digits, radix)? / DIGITS_PER_TERM).ceil() as i128 + 1)
+}
+
+/// Float precision in bits needed for `digits` correct digits in `radix`.
+pub fn precision_for_digits(digits: u32, radix: i32) -> Result<u32, PiDigitsError> {
+    let bits = (decimal_digits(digits, radix)? * std::f64::consts::LOG2_10).ceil();
+    if bits > rug::float::prec_max() as f64 {
+        return Err(PiDigitsError::TooManyDigits(digits));
+    }
+    Ok(bits as u32)
+}
+
+/// Decimal digits to compute for `digits` digits in `radix`, guard digits included.
+fn decimal_digits(digits: u32, radix: i32) -> Result<f64, PiDigitsError> {
+    Ok((u64::from(digits) + u64::from(GUARD_DIGITS)) as f64 * digits_scale(radix)?)
+}
+
+/// Evaluates pi = 426880 * sqrt(10005) / S for the merged Chudnovsky sum S.
+pub fn compute_pi(sum: &Rational, digits: u32, radix: i32) -> Result<Float, PiDigitsError> {
+    let prec = precision_for_digits(digits, radix)?;
+    let numerator = Float::with_val(prec, 10005).sqrt() * 426880;
+    Ok(numerator / Float::with_val(prec, sum))
+}
+
+/// Returns pi as "3." followed by exactly `digits` truncated digits in `radix`.
+pub fn pi_digits(sum: &ChudnovskySum, digits: u32, radix: i32) -> Result<String, PiDigitsError> {
+    if sum.n_start != 0 {
+        return Err(PiDigitsError::SumDoesNotStartAtZero(sum.n_start));
+    }
+    let needed = terms_needed(digits, radix)?;
+    if sum.n_end < needed {
+        return Err(PiDigitsError::NotEnoughTerms(sum.n_end, needed));
+    }
+
+    let pi = compute_pi(&sum.sum, digits, radix)?;
+    Ok(truncate_to_digits(pi, digits, radix))
+}
+
+/// Returns pi to `digits` digits using MPFR's own pi constant, which does not
+/// use the Chudnovsky series.
+pub fn reference_pi_digits(digits: u32, radix: i32) -> Result<String, PiDigitsError> {
+    let pi = Float::with_val(precision_for_digits(digits, radix)?, Constant::Pi);
+    Ok(truncate_to_digits(pi, digits, radix))
+}
+
+/// Compares `digit_string` ("3." followed by the digits) with an independent
+/// computation of pi and returns how many digits after the point were checked.
+pub fn verify_pi_digits(digit_string: &str, radix: i32) -> Result<usize, PiDigitsError> {
+    let digit_string = digit_string.trim().to_lowercase();
+    let digits = match digit_string.strip_prefix("3.") {
+        Some(digits) if !digits.is_empty() => digits,
+        _ => {
+            return Err(PiDigitsError::InvalidDigits(
+                "expected 3. followed by digits".to_string(),
+            ))
+        }
+    };
+    let count = u32::try_from(digits.len())
+        .map_err(|_| PiDigitsError::InvalidDigits("too many digits".to_string()))?;
+    let reference = reference_pi_digits(count, radix)?;
+
+    for (position, (expected, found)) in reference[2..].chars().zip(digits.chars()).enumerate() {
+        if expected != found {
+            return Err(PiDigitsError::DigitMismatch(position + 1, expected, found));
+        }
+    }
+    Ok(digits.len())
+}
+
+/// Computes the sum needed for `digits` digits from scratch and returns pi.
+pub fn pi_digits_from_scratch(digits: u32, radix: i32) -> Result<String, PiDigitsError> {
+    let split = BinarySplit::new(0, terms_needed(digits, radix)?).unwrap();
+    pi_digits(&ChudnovskySum::from(&split), digits, radix)
+}
+
+pub fn write_pi_digits(digit_string: &str, output_path: &str) -> Result<(), PiDigitsError> {
+    let mut file =
+        File::create(output_path).map_err(|e| PiDigitsError::WriteError(e.to_string()))?;
+    file.write_all(digit_string.as_bytes())
+        .and_then(|_| file.write_all(b"\n"))
+        .and_then(|_| file.sync_all())
+        .map_err(|e| PiDigitsError::WriteError(e.to_string()))
+}
+
+fn truncate_to_digits(pi: Float, digits: u32, radix: i32) -> String {
+    let scaled = pi * Integer::from(radix).pow(digits);
+    let (truncated, _) = scaled.to_integer_round(Round::Down).unwrap();
+    let mut digit_string = truncated.to_string_radix(radix);
+    digit_string.insert(1, '.');
+    digit_string
+}
+
+fn digits_scale(radix: i32) -> Result<f64, PiDigitsError> {
+    match radix {
+        10 => Ok(1.0),
+        16 => Ok(16_f64.log10()),
+        _ => Err(PiDigitsError::UnsupportedRadix(radix)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decimal_digits() {
+        let digits = pi_digits_from_scratch(50, 10).unwrap();
+        assert_eq!(
+            digits,
+            "3.14159265358979323846264338327950288419716939937510"
+        );
+    }
+
+    #[test]
+    fn test_hex_digits() {
+        let digits = pi_digits_from_scratch(24, 16).unwrap();
+        assert_eq!(digits, "3.243f6a8885a308d313198a2e");
+    }
+
+    #[test]
+    fn test_not_enough_terms() {
+        let sum = ChudnovskySum::from(&BinarySplit::new(0, 2).unwrap());
+        assert_eq!(
+            pi_digits(&sum, 100, 10),
+            Err(PiDigitsError::NotEnoughTerms(2, 10))
+        );
+        let sum = ChudnovskySum::from(&BinarySplit::new(2, 12).unwrap());
+        assert_eq!(
+            pi_digits(&sum, 100, 10),
+            Err(PiDigitsError::SumDoesNotStartAtZero(2))
+        );
+    }
+
+    #[test]
+    fn test_digit_count_past_u32() {
+        assert_eq!(terms_needed(u32::MAX, 10), Ok(302_853_906));
+        assert_eq!(
+            precision_for_digits(u32::MAX, 10),
+            Err(PiDigitsError::TooManyDigits(u32::MAX))
+        );
+        assert_eq!(precision_for_digits(1_000_000, 10), Ok(3_321_982));
+    }
+
+    #[test]
+    fn test_verify_digits() {
+        let digits = pi_digits_from_scratch(500, 10).unwrap();
+        assert_eq!(digits, reference_pi_digits(500, 10).unwrap());
+        assert_eq!(verify_pi_digits(&digits, 10), Ok(500));
+
+        let hex_digits = pi_digits_from_scratch(300, 16).unwrap();
+        assert_eq!(verify_pi_digits(&hex_digits, 16), Ok(300));
+    }
+
+    #[test]
+    fn test_verify_reports_first_mismatch() {
+        let mut digits = pi_digits_from_scratch(100, 10).unwrap();
+        // Digit 37 after the point is a 1
+        digits.replace_range(38..39, "4");
+        assert_eq!(
+            verify_pi_digits(&digits, 10),
+            Err(PiDigitsError::DigitMismatch(37, '1', '4'))
+        );
+        assert!(matches!(
+            verify_pi_digits("2.71828", 10),
+            Err(PiDigitsError::InvalidDigits(_))
+        ));
+    }
+
+    #[test]
+    fn test_write_digits() {
+        std::fs::create_dir_all("./testing").unwrap();
+        let digits = pi_digits_from_scratch(20, 10).unwrap();
+        write_pi_digits(&digits, "./testing/pi_digits.txt").unwrap();
+        let written = std::fs::read_to_string("./testing/pi_digits.txt").unwrap();
+        assert_eq!(written, "3.14159265358979323846\n");
+    }
+}
@@ -1,10 +1,21 @@
-use std::ops::{Add, Div, Mul, Sub};
+use std::fmt;
+use std::fs::{read_dir, remove_dir_all, File};
+use std::io::{BufRead, BufReader, Error, ErrorKind, Write};
+use std::ops::{Add, Mul, Sub};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 use rug::ops::Pow;
-use rug::{Complete, Integer};
+use rug::{Complete, Integer, Rational};
 use tokio::sync::mpsc;
 
-use crate::data_handler::DataWriter;
+use crate::checkpoint::{Checkpoint, CheckpointError};
+use crate::config::WorkerConfig;
+use crate::data_handler::{DataWriter, DataWriterError};
 
 use crate::status_handler::PercentUpdate;
 
@@ -12,13 +23,51 @@ use crate::status_handler::PercentUpdate;
 //     RequiredValueNotSet(String),
 // }
 
+/// 640320^3 / 24, the constant factor in the denominator of every Chudnovsky term ratio.
+const C3_OVER_24: u64 = 10_939_058_860_032_000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BinarySplitError {
+    EmptyRange(i128, i128),
+    RangesNotContiguous(i128, i128),
+}
+
+impl fmt::Display for BinarySplitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BinarySplitError::EmptyRange(_a, _b) => {
+                write!(f, "The range [{}, {}) does not contain any terms", _a, _b)
+            }
+            BinarySplitError::RangesNotContiguous(_e, _s) => write!(
+                f,
+                "Cannot merge a range ending at {} with a range starting at {}",
+                _e, _s
+            ),
+        }
+    }
+}
+
 pub struct CalcPi {
     n_start: i128,
     n_end: i128,
     status_update_interval: Option<i128>,
+    n_next: i128,
+
+    checkpoint_path: Option<String>,
+    checkpoint_interval: Option<i128>,
+
+    threads: usize,
+    /// Write one binary splitting triple per this many terms instead of every term.
+    terms_per_triple: Option<i128>,
+    /// Stops the job, checkpointed, at the next term once set.
+    cancel: Arc<AtomicBool>,
 
     recursion_ready: bool,
 
+    /// When this run started computing, and how long runs before a resume took.
+    started: Instant,
+    compute_before: Duration,
+
     data_handler: DataWriter,
 
     last_n: Integer,
@@ -29,62 +78,353 @@ pub struct CalcPi {
 }
 
 impl CalcPi {
-    pub fn new(n_start: i128, n_end: i128, base_output_path: Option<&str>) -> Self {
-        CalcPi {
+    pub fn new(
+        n_start: i128,
+        n_end: i128,
+        base_output_path: Option<&str>,
+    ) -> Result<Self, DataWriterError> {
+        CalcPi::with_output_format(n_start, n_end, base_output_path, "csv")
+    }
+
+    /// Like `new`, but writes the terms as `file_type` ("csv", "jsonl" or "bin").
+    pub fn with_output_format(
+        n_start: i128,
+        n_end: i128,
+        base_output_path: Option<&str>,
+        file_type: &str,
+    ) -> Result<Self, DataWriterError> {
+        Ok(CalcPi {
             n_start,
             n_end,
             status_update_interval: None,
+            n_next: n_start,
+            checkpoint_path: None,
+            checkpoint_interval: None,
+            threads: 1,
+            terms_per_triple: None,
+            cancel: Arc::new(AtomicBool::new(false)),
             recursion_ready: false,
-            data_handler: DataWriter::new("csv", base_output_path),
+            started: Instant::now(),
+            compute_before: Duration::ZERO,
+            data_handler: DataWriter::new(file_type, base_output_path)?,
             last_n: Integer::from(0),
             last_l: Integer::from(0),
             last_m: Integer::from(0),
             last_x: Integer::from(0),
             _k: Integer::from(0),
-        }
+        })
+    }
+
+    /// Picks up a job from the latest valid checkpoint at `checkpoint_path`.
+    pub fn resume_from_checkpoint(checkpoint_path: &str) -> Result<Self, CheckpointError> {
+        let checkpoint = Checkpoint::load_latest(checkpoint_path)?;
+        let invalid = || CheckpointError::NoValidCheckpoint(checkpoint_path.to_string());
+        let parse = |s: &str| Integer::from_str_radix(s, 10).map_err(|_| invalid());
+
+        let last_n = parse(&checkpoint.last_n)?;
+        let n_next = last_n.to_i128().ok_or_else(invalid)? + 1;
+        let data_handler = DataWriter::resume(&checkpoint.writer).map_err(|_| invalid())?;
+        println!("Resuming from checkpoint at n={}", last_n);
+        Ok(CalcPi {
+            n_start: checkpoint.n_start,
+            n_end: checkpoint.n_end,
+            status_update_interval: None,
+            n_next,
+            checkpoint_path: None,
+            checkpoint_interval: None,
+            threads: 1,
+            terms_per_triple: checkpoint.terms_per_triple,
+            cancel: Arc::new(AtomicBool::new(false)),
+            recursion_ready: true,
+            started: Instant::now(),
+            compute_before: Duration::from_secs_f64(checkpoint.compute_secs),
+            data_handler,
+            last_n,
+            last_l: parse(&checkpoint.last_l)?,
+            last_m: parse(&checkpoint.last_m)?,
+            last_x: parse(&checkpoint.last_x)?,
+            _k: parse(&checkpoint.k)?,
+        })
     }
 
     pub fn set_status_update_interval(&mut self, interval: i128) {
         self.status_update_interval = Some(interval);
     }
 
+    /// Applies the output settings from `config`, and its status interval if set.
+    pub fn apply_config(&mut self, config: &WorkerConfig) {
+        self.data_handler
+            .set_max_size_per_file(config.max_size_per_file);
+        self.data_handler
+            .set_compression_level(config.compression_level);
+        if let Some(interval) = config.status_update_interval {
+            self.set_status_update_interval(interval);
+        }
+    }
+
+    /// Writes a checkpoint to `path` every `interval` terms.
+    pub fn set_checkpoint(&mut self, path: &str, interval: i128) {
+        self.checkpoint_path = Some(path.to_string());
+        self.checkpoint_interval = Some(interval);
+    }
+
+    /// Makes the job stop with `ErrorKind::Interrupted` at the next term
+    /// after `cancel` is set, once its data is synced and checkpointed.
+    pub fn set_cancel_flag(&mut self, cancel: Arc<AtomicBool>) {
+        self.cancel = cancel;
+    }
+
+    /// Splits the remaining terms across `threads` worker threads.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// Writes the binary splitting triple of every `terms` terms as an
+    /// `n_start,n_end,p,q,t` row instead of an `n,l,m,x` row per term, which
+    /// keeps archives small late in the series where X runs to millions of digits.
+    pub fn set_terms_per_triple(&mut self, terms: i128) {
+        self.terms_per_triple = Some(terms.max(1));
+    }
+
+    /// Whether this job computes terms `n_start..n_end` into `file_type` files,
+    /// writing a triple every `terms_per_triple` terms if that is set.
+    pub fn is_job(
+        &self,
+        n_start: i128,
+        n_end: i128,
+        file_type: &str,
+        terms_per_triple: Option<i128>,
+    ) -> bool {
+        (self.n_start, self.n_end, self.terms_per_triple) == (n_start, n_end, terms_per_triple)
+            && self.data_handler.file_type() == file_type
+    }
+
+    /// Deletes the data files written so far, for a job that will not be finished.
+    pub fn remove_output(&self) {
+        remove_dir_all(self.data_handler.master_path()).unwrap_or(());
+    }
+
     pub fn calc_pi_terms(&mut self) -> std::io::Result<()> {
-        self.init_data_handler();
-        for n in self.n_start..self.n_end {
-            self.calc_l_m_x(Integer::from(n));
-            self.write_most_recent_l_m_x();
+        self.started = Instant::now();
+        if self.threads > 1 {
+            let progress = Arc::new(AtomicU64::new(0));
+            let handles = self.spawn_parts(&progress)?;
+            let parts = self.join_parts(handles)?;
+            self.stitch_parts(parts)?;
+        } else {
+            self.init_data_handler()?;
+            for n in self.n_next..self.n_end {
+                self.check_cancelled()?;
+                self.write_term(n)?;
+                self.checkpoint_if_due(n);
+            }
         }
-        self.data_handler.close_and_compress_output().unwrap();
+        self.close_and_compress_output()?;
+        self.remove_checkpoint();
         Ok(())
     }
 
-    pub async fn calc_pi_terms_with_status(&mut self, tx: mpsc::Sender<PercentUpdate>) {
+    /// Like `calc_pi_terms`, reporting progress on `tx` every status update interval.
+    pub async fn calc_pi_terms_with_status(
+        &mut self,
+        tx: mpsc::Sender<PercentUpdate>,
+    ) -> std::io::Result<()> {
         let range = self.n_end - self.n_start;
-        if self.status_update_interval.is_none() {
-            panic!("Status update interval not set");
+        let interval = self
+            .status_update_interval
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Status update interval not set"))?;
+        self.started = Instant::now();
+        if self.threads > 1 {
+            self.calc_parts_with_status(&tx).await?;
+        } else {
+            self.init_data_handler()?;
+            for n in self.n_next..self.n_end {
+                if n % interval == 0 {
+                    let percent_complete = (n - self.n_start) as f32 / range as f32 * 100.0;
+                    println!("Percent complete: {} {}", percent_complete, n);
+                    send_percent(&tx, percent_complete).await?;
+                    tokio::time::sleep(std::time::Duration::from_millis(0)).await;
+                }
+                self.check_cancelled()?;
+                self.write_term(n)?;
+                self.checkpoint_if_due(n);
+            }
         }
-        self.init_data_handler();
-        for n in self.n_start..self.n_end {
-            if n % self.status_update_interval.unwrap() == 0 {
-                let percent_complete = (n - self.n_start) as f32 / range as f32 * 100.0;
-                println!("Percent complete: {} {}", percent_complete, n);
-                tx.send(PercentUpdate::new(percent_complete)).await.unwrap();
-                tokio::time::sleep(std::time::Duration::from_millis(0)).await;
+        send_percent(&tx, 100.0).await?;
+        self.close_and_compress_output()?;
+        self.remove_checkpoint();
+        Ok(())
+    }
+
+    async fn calc_parts_with_status(
+        &mut self,
+        tx: &mpsc::Sender<PercentUpdate>,
+    ) -> std::io::Result<()> {
+        let range = self.n_end - self.n_start;
+        let interval = self.status_update_interval.unwrap();
+        let progress = Arc::new(AtomicU64::new(0));
+        let handles = self.spawn_parts(&progress)?;
+
+        let mut next_update = 0;
+        while handles.iter().any(|handle| !handle.is_finished()) {
+            let done = self.n_next - self.n_start + progress.load(Ordering::Relaxed) as i128;
+            if done >= next_update {
+                let percent_complete = done as f32 / range as f32 * 100.0;
+                println!("Percent complete: {} {}", percent_complete, done);
+                send_percent(tx, percent_complete).await?;
+                next_update = (done / interval + 1) * interval;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+        }
+        let parts = self.join_parts(handles)?;
+        self.stitch_parts(parts)
+    }
+
+    /// Starts one thread per contiguous slice of the remaining terms. Each
+    /// thread runs its own `CalcPi`, seeded through the factorial path, and
+    /// writes into its own directory inside this job's output directory, or
+    /// carries on in the directory of the earlier run its checkpoint is from.
+    fn spawn_parts(
+        &mut self,
+        progress: &Arc<AtomicU64>,
+    ) -> std::io::Result<Vec<JoinHandle<std::io::Result<CalcPi>>>> {
+        let remaining = self.n_end - self.n_next;
+        let threads = (self.threads as i128).clamp(1, remaining.max(1));
+        let base_path = self.data_handler.master_path().to_string();
+        let mut handles = vec![];
+        for k in 0..threads {
+            let a = self.n_next + remaining * k / threads;
+            let b = self.n_next + remaining * (k + 1) / threads;
+            let mut part = self.part(k, a, b, &base_path)?;
+            progress.fetch_add((part.n_next - part.n_start) as u64, Ordering::Relaxed);
+            let progress = Arc::clone(progress);
+            handles.push(thread::spawn(move || {
+                part.calc_part(&progress)?;
+                Ok(part)
+            }));
+        }
+        // Parts past the last one are left from a run with more threads
+        if let Some(path) = &self.checkpoint_path {
+            for k in threads.. {
+                let part_path = format!("{}.part{}", path, k);
+                if !Path::new(&part_path).exists() {
+                    break;
+                }
+                discard_part(&part_path);
             }
-            self.calc_l_m_x(Integer::from(n));
-            self.write_most_recent_l_m_x();
         }
-        tx.send(PercentUpdate::new(100.0)).await.unwrap();
-        self.data_handler.close_and_compress_output().unwrap();
+        Ok(handles)
+    }
+
+    /// Builds the `CalcPi` for slice `k`, resuming it if its checkpoint
+    /// covers exactly [a, b). A checkpoint for any other slice is from a run
+    /// split differently, so it is removed along with its data files.
+    fn part(&self, k: i128, a: i128, b: i128, base_path: &str) -> Result<CalcPi, DataWriterError> {
+        let checkpoint_path = self
+            .checkpoint_path
+            .as_ref()
+            .map(|path| format!("{}.part{}", path, k));
+        let resumed = checkpoint_path
+            .as_deref()
+            .and_then(|path| CalcPi::resume_from_checkpoint(path).ok());
+        let mut part = match resumed {
+            Some(part)
+                if part.is_job(a, b, self.data_handler.file_type(), self.terms_per_triple) =>
+            {
+                part
+            }
+            _ => {
+                if let Some(path) = &checkpoint_path {
+                    discard_part(path);
+                }
+                let mut part = CalcPi::with_output_format(
+                    a,
+                    b,
+                    Some(base_path),
+                    self.data_handler.file_type(),
+                )?;
+                part.terms_per_triple = self.terms_per_triple;
+                part
+            }
+        };
+        part.data_handler
+            .set_max_size_per_file(self.data_handler.max_size_per_file());
+        part.data_handler
+            .set_compression_level(self.data_handler.compression_level());
+        part.set_cancel_flag(Arc::clone(&self.cancel));
+        if let (Some(path), Some(interval)) = (checkpoint_path, self.checkpoint_interval) {
+            part.set_checkpoint(&path, interval);
+        }
+        Ok(part)
+    }
+
+    /// Waits for every part. If they stop early and all carried on in an
+    /// earlier run's directory, this run's own directory is removed.
+    fn join_parts(
+        &self,
+        handles: Vec<JoinHandle<std::io::Result<CalcPi>>>,
+    ) -> std::io::Result<Vec<CalcPi>> {
+        join_parts(handles)
+            .inspect_err(|_| remove_dir_without_parts(Path::new(self.data_handler.master_path())))
+    }
+
+    fn calc_part(&mut self, progress: &AtomicU64) -> std::io::Result<()> {
+        self.init_data_handler()?;
+        for n in self.n_next..self.n_end {
+            self.check_cancelled()?;
+            self.write_term(n)?;
+            self.checkpoint_if_due(n);
+            progress.fetch_add(1, Ordering::Relaxed);
+        }
+        self.data_handler.state()?;
+        Ok(())
+    }
+
+    /// Moves the files of every part, in order, into this job's output directory.
+    fn stitch_parts(&mut self, parts: Vec<CalcPi>) -> std::io::Result<()> {
+        // Resumed parts ran side by side before, so the slowest one is what it took
+        self.compute_before = parts
+            .iter()
+            .map(|part| part.compute_before)
+            .max()
+            .unwrap_or_default();
+        for mut part in parts {
+            self.data_handler
+                .append_files_from(&mut part.data_handler)?;
+            part.remove_checkpoint();
+            if let Some(dir) = Path::new(part.data_handler.master_path()).parent() {
+                if dir != Path::new(self.data_handler.master_path()) {
+                    remove_dir_without_parts(dir);
+                }
+            }
+        }
+        Ok(())
     }
 
-    pub fn set_data_handler_archive_id(&mut self, id: i32, batch_id: i32) {
+    /// Archives the data files behind a manifest of this job.
+    fn close_and_compress_output(&mut self) -> Result<(), DataWriterError> {
+        let compute = self.compute_time();
+        self.data_handler
+            .close_and_compress_with_manifest(self.n_start, self.n_end, compute)?;
+        Ok(())
+    }
+
+    fn compute_time(&self) -> Duration {
+        self.compute_before + self.started.elapsed()
+    }
+
+    pub fn set_data_handler_archive_id(&mut self, id: u64, batch_id: u64) {
         self.data_handler.set_archive_id(id, batch_id);
     }
 
+    /// Where the finished job's archive is written.
+    pub fn archive_path(&self) -> String {
+        self.data_handler.archive_path()
+    }
+
     #[cfg(bench)]
     pub fn calc_pi_no_write(&mut self) -> std::io::Result<()> {
-        self.init_data_handler();
+        self.init_data_handler()?;
         for n in self.n_start..self.n_end {
             self.calc_l_m_x(Integer::from(n));
         }
@@ -93,70 +433,531 @@ impl CalcPi {
     }
 
     fn calc_l_m_x(&mut self, n: Integer) {
-        let _n: u32 = n.to_u32().unwrap();
-
         if self.recursion_ready && self.last_n != Integer::sub(n.clone(), 1) {
             println!("Recursion not ready at n={}, last_n={}", n, self.last_n);
             self.recursion_ready = false;
         }
         self.last_n = n;
         if !self.recursion_ready {
-            // calc init m value
+            (self.last_l, self.last_m, self.last_x) = seed_l_m_x(&self.last_n);
 
-            let _q = Integer::factorial(6 * &_n).complete();
-            let _w = Integer::factorial(3 * &_n).complete();
-            let _e = Integer::pow(Integer::factorial(_n).complete(), 3);
-            self.last_m = _q / (_w * _e);
-
-            let _kh: i128 = -6 + (12 * &_n) as i128;
-            self._k = Integer::from(_kh);
-
-            // calc init l value
-            let _a = Integer::mul(Integer::from(545140134), &_n);
-            self.last_l = Integer::add(_a, 13591409);
-
-            // calc init x value
-            self.last_x = Integer::pow(Integer::from(-262537412640768000_i64), &_n);
+            self._k = Integer::from(12 * &self.last_n) - 6;
 
             self.recursion_ready = true;
         } else {
             self.last_l = Integer::from(&self.last_l + 545140134);
             self.last_x = Integer::from(&self.last_x * -262537412640768000_i128);
-            self._k = &self._k + Integer::from(12 * &_n);
+            self._k += 12;
 
             let _q = Integer::pow(Integer::from(&self._k), 3);
             let _w = Integer::mul(Integer::from(16), &self._k);
-            let _e = Integer::pow(Integer::from(_n as i128), 3);
+            let _e = Integer::pow(Integer::from(&self.last_n), 3);
 
             let _num: Integer = Integer::sub(_q, _w);
 
-            let _last_m_temp = self.last_m.clone();
-            self.last_m = Integer::div(_num, _e);
-            self.last_m = Integer::mul(Integer::from(&self.last_m), &_last_m_temp);
+            // Multiply before dividing, (K^3 - 16K) / n^3 alone is not an integer
+            self.last_m *= _num;
+            self.last_m /= _e;
+        }
+    }
+
+    /// Computes term `n` and writes its row, or in triple mode writes the
+    /// triple of the chunk once `n` is its last term.
+    fn write_term(&mut self, n: i128) -> Result<(), DataWriterError> {
+        let Some(terms) = self.terms_per_triple else {
+            self.calc_l_m_x(Integer::from(n));
+            return self.write_most_recent_l_m_x();
+        };
+        if (n + 1 - self.n_start) % terms != 0 && n + 1 != self.n_end {
+            return Ok(());
         }
+        let split = BinarySplit::split(n - (n - self.n_start) % terms, n + 1);
+        self.data_handler.write_integers(&[
+            &Integer::from(split.n_start),
+            &Integer::from(split.n_end),
+            &split.p,
+            &split.q,
+            &split.t,
+        ])?;
+        // Checkpoints resume after last_n, so it only moves once a chunk is written
+        self.last_n = Integer::from(n);
+        self.recursion_ready = true;
+        Ok(())
     }
 
-    fn write_most_recent_l_m_x(&mut self) {
-        let data = vec![
-            self.last_n.to_string(),
-            self.last_l.to_string(),
-            self.last_m.to_string(),
-            self.last_x.to_string(),
-        ];
+    fn write_most_recent_l_m_x(&mut self) -> Result<(), DataWriterError> {
         self.data_handler
-            .write_data_using_array(data, Some(true))
-            .unwrap();
+            .write_integers(&[&self.last_n, &self.last_l, &self.last_m, &self.last_x])
     }
 
-    fn init_data_handler(&mut self) {
+    fn checkpoint_if_due(&mut self, n: i128) {
+        match self.checkpoint_interval {
+            Some(interval) if (n + 1 - self.n_start) % interval == 0 => {}
+            _ => return,
+        }
+        // A failed checkpoint only costs progress on restart, so keep computing
+        if let Err(e) = self.write_checkpoint() {
+            println!("Could not checkpoint at n={}: {}", n, e);
+        }
+    }
+
+    /// Syncs the data written so far and, if checkpointing is on and a term
+    /// has been computed, saves where the job is up to.
+    fn write_checkpoint(&mut self) -> std::io::Result<()> {
+        let writer = self.data_handler.state()?;
+        let path = match &self.checkpoint_path {
+            Some(path) if self.recursion_ready => path.clone(),
+            _ => return Ok(()),
+        };
+        let checkpoint = Checkpoint {
+            n_start: self.n_start,
+            n_end: self.n_end,
+            last_n: self.last_n.to_string(),
+            last_l: self.last_l.to_string(),
+            last_m: self.last_m.to_string(),
+            last_x: self.last_x.to_string(),
+            k: self._k.to_string(),
+            compute_secs: self.compute_time().as_secs_f64(),
+            terms_per_triple: self.terms_per_triple,
+            writer,
+        };
+        checkpoint
+            .save(&path)
+            .map_err(|e| Error::other(e.to_string()))
+    }
+
+    fn check_cancelled(&mut self) -> std::io::Result<()> {
+        if !self.cancel.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        self.write_checkpoint()?;
+        Err(Error::new(ErrorKind::Interrupted, "The job was stopped"))
+    }
+
+    fn remove_checkpoint(&self) {
+        if let Some(path) = &self.checkpoint_path {
+            Checkpoint::remove(path);
+        }
+    }
+
+    fn init_data_handler(&mut self) -> Result<(), DataWriterError> {
+        // A resumed job already has its headers on disk
+        if self.n_next != self.n_start {
+            return Ok(());
+        }
+        let headers = match self.terms_per_triple {
+            Some(_) => ["n_start", "n_end", "p", "q", "t"].as_slice(),
+            None => ["n", "l", "m", "x"].as_slice(),
+        };
         self.data_handler
-            .assign_headers(vec![
-                "n".to_string(),
-                "l".to_string(),
-                "m".to_string(),
-                "x".to_string(),
-            ])
-            .unwrap();
+            .assign_headers(headers.iter().map(|h| h.to_string()).collect())
+    }
+}
+
+/// Removes the checkpoint of a part that will not be resumed and the data
+/// files it wrote.
+fn discard_part(checkpoint_path: &str) {
+    if let Ok(part) = CalcPi::resume_from_checkpoint(checkpoint_path) {
+        part.remove_output();
+        if let Some(dir) = Path::new(part.data_handler.master_path()).parent() {
+            remove_dir_without_parts(dir);
+        }
+    }
+    Checkpoint::remove(checkpoint_path);
+}
+
+/// Removes the output directory of a threaded job once none of its parts'
+/// directories are left in it, which for any but the current run means its
+/// parts were all resumed or discarded.
+fn remove_dir_without_parts(dir: &Path) {
+    let is_output_dir = dir
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with("output_"));
+    let has_parts = read_dir(dir)
+        .map(|entries| entries.flatten().any(|entry| entry.path().is_dir()))
+        .unwrap_or(true);
+    if is_output_dir && !has_parts {
+        remove_dir_all(dir).unwrap_or(());
+    }
+}
+
+/// Waits for every part thread, in order, before returning the first error,
+/// so no part is still writing once the job has stopped.
+fn join_parts(handles: Vec<JoinHandle<std::io::Result<CalcPi>>>) -> std::io::Result<Vec<CalcPi>> {
+    let results: Vec<std::io::Result<CalcPi>> = handles
+        .into_iter()
+        .map(|handle| {
+            handle
+                .join()
+                .map_err(|_| Error::other("a worker thread panicked"))?
+        })
+        .collect();
+    results.into_iter().collect()
+}
+
+async fn send_percent(tx: &mpsc::Sender<PercentUpdate>, percent: f32) -> std::io::Result<()> {
+    tx.send(PercentUpdate::new(percent)).await.map_err(|_| {
+        Error::new(
+            ErrorKind::BrokenPipe,
+            "nobody is listening for status updates",
+        )
+    })
+}
+
+/// L, M and X for term `n` computed straight from factorials, without the recurrence.
+pub fn closed_form_l_m_x(n: &Integer) -> (Integer, Integer, Integer) {
+    // calc init m value
+    let _q = factorial(&Integer::from(6 * n));
+    let _w = factorial(&Integer::from(3 * n));
+    let _e = Integer::pow(factorial(n), 3);
+    let m = _q / (_w * _e);
+
+    // calc init l value
+    let _a = Integer::mul(Integer::from(545140134), n);
+    let l = Integer::add(_a, 13591409);
+
+    // calc init x value
+    let x = pow(&Integer::from(-262537412640768000_i64), n);
+
+    (l, m, x)
+}
+
+/// L, M and X for term `n`, the same values as `closed_form_l_m_x` but much
+/// faster for a job starting far out in the series.
+///
+/// M = (6n)! / ((3n)! (n!)^3) is built from its prime factorization, so the
+/// factorials are never formed, and X = (-640320^3)^n is split into a power
+/// of 10005 and a shift, as 640320 = 2^6 * 10005. The primes up to 6n come
+/// from a segmented sieve, so this holds for any n with 6n in a u64; only
+/// past that does it fall back to the factorials.
+pub fn seed_l_m_x(n: &Integer) -> (Integer, Integer, Integer) {
+    let Some(limit) = n.to_u64().and_then(|n| n.checked_mul(6)) else {
+        return closed_form_l_m_x(n);
+    };
+    let (n_6, n_3, n_1) = (limit, limit / 2, limit / 6);
+
+    let l = Integer::from(545140134 * n) + 13591409;
+
+    let mut factors = Vec::new();
+    for_each_prime(limit, |p| {
+        let e = legendre(n_6, p) - legendre(n_3, p) - 3 * legendre(n_1, p);
+        if e > 0 {
+            factors.push(Integer::from(p).pow(e as u32));
+        }
+    });
+    let m = product(&factors);
+
+    // 10005^(3n) needs 3n in a u32; past that the plain power is no slower
+    let x = match n_1.checked_mul(3).and_then(|e| u32::try_from(e).ok()) {
+        Some(e) => {
+            let x = Integer::from(10005).pow(e) << (6 * e as usize);
+            if n.is_odd() {
+                -x
+            } else {
+                x
+            }
+        }
+        None => pow(&Integer::from(-262537412640768000_i64), n),
+    };
+
+    (l, m, x)
+}
+
+/// The exponent of the prime `p` in m!, by Legendre's formula.
+fn legendre(m: u64, p: u64) -> u64 {
+    let mut exponent = 0;
+    let mut power = p;
+    while power <= m {
+        exponent += m / power;
+        power = match power.checked_mul(p) {
+            Some(power) => power,
+            None => break,
+        };
+    }
+    exponent
+}
+
+/// Every prime up to `limit`, from a sieve over the odd numbers.
+fn primes_up_to(limit: u64) -> Vec<u64> {
+    if limit < 2 {
+        return vec![];
+    }
+    // Bit i stands for 2i + 1
+    let odd_count = (limit as usize).div_ceil(2);
+    let mut composite = vec![0u64; odd_count.div_ceil(64)];
+    let mut primes = vec![2];
+    for i in 1..odd_count {
+        if composite[i / 64] & (1 << (i % 64)) != 0 {
+            continue;
+        }
+        let p = 2 * i + 1;
+        primes.push(p as u64);
+        if p > limit as usize / p {
+            continue;
+        }
+        let mut j = p * p / 2;
+        while j < odd_count {
+            composite[j / 64] |= 1 << (j % 64);
+            j += p;
+        }
+    }
+    primes
+}
+
+/// Odd numbers per segment of `for_each_prime`, a 32 KiB bitmap.
+const SEGMENT_ODDS: u64 = 1 << 18;
+
+/// Calls `f` with every prime up to `limit` in order, sieving the odd numbers
+/// a segment at a time so the memory stays bounded by sqrt(limit).
+fn for_each_prime(limit: u64, mut f: impl FnMut(u64)) {
+    if limit < 2 {
+        return;
+    }
+    f(2);
+    let base = primes_up_to(limit.isqrt());
+    // Bit i of a segment stands for 2 (low + i) + 1
+    let odd_count = limit.div_ceil(2);
+    let mut low = 1;
+    let mut composite = vec![0u64; (SEGMENT_ODDS / 64) as usize];
+    while low < odd_count {
+        let high = (low + SEGMENT_ODDS).min(odd_count);
+        composite.fill(0);
+        for &p in base.iter().skip(1) {
+            // Odd multiples of p sit at the bits j = p/2 (mod p); start from
+            // the first in the segment, or from p^2 if that is further on
+            let mut j = (low + (p / 2 + p - low % p) % p).max(p * p / 2);
+            while j < high {
+                let i = j - low;
+                composite[(i / 64) as usize] |= 1 << (i % 64);
+                j += p;
+            }
+        }
+        for i in 0..high - low {
+            if composite[(i / 64) as usize] & (1 << (i % 64)) == 0 {
+                f(2 * (low + i) + 1);
+            }
+        }
+        low = high;
+    }
+}
+
+/// The product of `factors`, split in halves so the operands stay balanced.
+fn product(factors: &[Integer]) -> Integer {
+    match factors {
+        [] => Integer::from(1),
+        [factor] => factor.clone(),
+        _ => {
+            let (left, right) = factors.split_at(factors.len() / 2);
+            product(left) * product(right)
+        }
+    }
+}
+
+/// n!, for n past the u32 that GMP's factorial takes too.
+fn factorial(n: &Integer) -> Integer {
+    match n.to_u32() {
+        Some(n) => Integer::factorial(n).complete(),
+        None => {
+            let u32_max = Integer::from(u32::MAX);
+            factorial(&u32_max) * range_product(&(u32_max + 1), n)
+        }
+    }
+}
+
+/// The product of a..=b, split in halves so the operands stay balanced.
+fn range_product(a: &Integer, b: &Integer) -> Integer {
+    if a > b {
+        return Integer::from(1);
+    }
+    if a == b {
+        return a.clone();
+    }
+    let mid = Integer::from(a + b) / 2;
+    range_product(a, &mid) * range_product(&(mid.clone() + 1), b)
+}
+
+/// base^exp, for exponents past the u32 that GMP's pow takes too.
+fn pow(base: &Integer, exp: &Integer) -> Integer {
+    match exp.to_u32() {
+        Some(exp) => Integer::pow(base.clone(), exp),
+        None => {
+            let mut result = pow(base, &Integer::from(exp >> 1)).square();
+            if exp.is_odd() {
+                result *= base;
+            }
+            result
+        }
+    }
+}
+
+/// The P/Q/T triple of the Chudnovsky series over the term range [n_start, n_end).
+///
+/// Triples of adjacent ranges can be merged, so a node can hand back a single
+/// result for its whole range instead of one row per term.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinarySplit {
+    pub n_start: i128,
+    pub n_end: i128,
+    pub p: Integer,
+    pub q: Integer,
+    pub t: Integer,
+}
+
+impl BinarySplit {
+    pub fn new(n_start: i128, n_end: i128) -> Result<Self, BinarySplitError> {
+        if n_start < 0 || n_end <= n_start {
+            return Err(BinarySplitError::EmptyRange(n_start, n_end));
+        }
+        Ok(BinarySplit::split(n_start, n_end))
+    }
+
+    pub fn merge(self, other: BinarySplit) -> Result<Self, BinarySplitError> {
+        if self.n_end != other.n_start {
+            return Err(BinarySplitError::RangesNotContiguous(
+                self.n_end,
+                other.n_start,
+            ));
+        }
+        Ok(self.merge_unchecked(other))
+    }
+
+    /// Exact value of sum(M_n * L_n / X_n) for n in [n_start, n_end).
+    ///
+    /// Seeding M and X at n_start - 1 costs far more than merging triples,
+    /// so merge neighbouring triples first and call this once; a triple from
+    /// n=0 needs no seeding at all.
+    pub fn partial_sum(&self) -> Rational {
+        // T/Q is relative to the term before n_start, so scale it back by
+        // prod(p_j / q_j) for j in [1, n_start), which is M / |X| at n_start - 1.
+        let mut sum = Rational::from((&self.t, &self.q));
+        if self.n_start > 1 {
+            let (_, m, x) = seed_l_m_x(&Integer::from(self.n_start - 1));
+            sum *= Rational::from((m, x.abs()));
+        }
+        sum
+    }
+
+    fn split(a: i128, b: i128) -> Self {
+        if b - a == 1 {
+            return BinarySplit::leaf(a);
+        }
+        let m = (a + b) / 2;
+        BinarySplit::split(a, m).merge_unchecked(BinarySplit::split(m, b))
+    }
+
+    fn leaf(a: i128) -> Self {
+        let (p, q) = if a == 0 {
+            (Integer::from(1), Integer::from(1))
+        } else {
+            let n = Integer::from(a);
+            let p = (Integer::from(6 * &n) - 5)
+                * (Integer::from(2 * &n) - 1)
+                * (Integer::from(6 * &n) - 1);
+            let q = Integer::pow(n, 3) * C3_OVER_24;
+            (p, q)
+        };
+        let l = Integer::from(545140134) * a + 13591409;
+        let mut t = Integer::from(&p * &l);
+        if a % 2 == 1 {
+            t = -t;
+        }
+        BinarySplit {
+            n_start: a,
+            n_end: a + 1,
+            p,
+            q,
+            t,
+        }
+    }
+
+    fn merge_unchecked(self, other: BinarySplit) -> Self {
+        let t = Integer::from(&other.q * &self.t) + Integer::from(&self.p * &other.t);
+        BinarySplit {
+            n_start: self.n_start,
+            n_end: other.n_end,
+            p: self.p * other.p,
+            q: self.q * other.q,
+            t,
+        }
+    }
+}
+
+/// The exact value of sum(M_n * L_n / X_n) over the term range [n_start, n_end).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChudnovskySum {
+    pub n_start: i128,
+    pub n_end: i128,
+    pub sum: Rational,
+}
+
+impl From<&BinarySplit> for ChudnovskySum {
+    fn from(split: &BinarySplit) -> Self {
+        ChudnovskySum {
+            n_start: split.n_start,
+            n_end: split.n_end,
+            sum: split.partial_sum(),
+        }
+    }
+}
+
+impl ChudnovskySum {
+    pub fn merge(self, other: ChudnovskySum) -> Result<Self, BinarySplitError> {
+        if self.n_end != other.n_start {
+            return Err(BinarySplitError::RangesNotContiguous(
+                self.n_end,
+                other.n_start,
+            ));
+        }
+        Ok(ChudnovskySum {
+            n_start: self.n_start,
+            n_end: other.n_end,
+            sum: self.sum + other.sum,
+        })
+    }
+
+    /// Writes the sum as a csv file with a single row, in the same layout as
+    /// the files written by `DataWriter`.
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let mut file = File::create(path)?;
+        writeln!(file, "n_start,n_end,numerator,denominator,")?;
+        writeln!(
+            file,
+            "{},{},{},{},",
+            self.n_start,
+            self.n_end,
+            self.sum.numer(),
+            self.sum.denom()
+        )?;
+        file.sync_all()
+    }
+
+    pub fn load(path: &str) -> std::io::Result<Self> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, format!("{}: {}", path, msg));
+
+        let header = lines.next().ok_or_else(|| invalid("missing header"))??;
+        if header != "n_start,n_end,numerator,denominator," {
+            return Err(invalid("unexpected header"));
+        }
+        let row = lines.next().ok_or_else(|| invalid("missing row"))??;
+        let values: Vec<&str> = row.trim_end_matches(',').split(',').collect();
+        if values.len() != 4 {
+            return Err(invalid("expected 4 values"));
+        }
+        let n_start = values[0].parse().map_err(|_| invalid("bad n_start"))?;
+        let n_end = values[1].parse().map_err(|_| invalid("bad n_end"))?;
+        let numerator =
+            Integer::from_str_radix(values[2], 10).map_err(|_| invalid("bad numerator"))?;
+        let denominator =
+            Integer::from_str_radix(values[3], 10).map_err(|_| invalid("bad denominator"))?;
+        if denominator == 0 {
+            return Err(invalid("zero denominator"));
+        }
+        Ok(ChudnovskySum {
+            n_start,
+            n_end,
+            sum: Rational::from((numerator, denominator)),
+        })
     }
 }
 
@@ -173,7 +974,7 @@ mod tests {
     #[test]
     fn test_init_calc_l_m_x() {
         let test_path = Some("./testing");
-        let mut _c = CalcPi::new(0_i128, 1_i128, test_path);
+        let mut _c = CalcPi::new(0_i128, 1_i128, test_path).unwrap();
         _c.calc_l_m_x(Integer::from(0));
         assert_eq!(_c.last_l, Integer::from(13591409));
         assert_eq!(_c.last_m, Integer::from(1));
@@ -185,7 +986,7 @@ mod tests {
     #[test]
     fn test_recursive_calc_l_m_x() {
         let test_path = Some("./testing");
-        let mut _c = CalcPi::new(0_i128, 1_i128, test_path);
+        let mut _c = CalcPi::new(0_i128, 1_i128, test_path).unwrap();
         _c.calc_l_m_x(Integer::from(0));
         _c.calc_l_m_x(Integer::from(1));
         // _c.calc_l_m_x(Integer::from(1));
@@ -195,10 +996,62 @@ mod tests {
         print!("l: {}, x: {}, m: {}", _c.last_l, _c.last_x, _c.last_m);
     }
 
+    #[test]
+    fn test_recursion_matches_closed_form() {
+        let test_path = Some("./testing");
+        let mut _c = CalcPi::new(0_i128, 1_i128, test_path).unwrap();
+        let mut _f = CalcPi::new(0_i128, 1_i128, test_path).unwrap();
+        for n in 0..12 {
+            _c.calc_l_m_x(Integer::from(n));
+            _f.recursion_ready = false;
+            _f.calc_l_m_x(Integer::from(n));
+            assert_eq!(_c.last_l, _f.last_l);
+            assert_eq!(_c.last_m, _f.last_m);
+            assert_eq!(_c.last_x, _f.last_x);
+        }
+    }
+
+    #[test]
+    fn test_seed_matches_closed_form() {
+        for n in (0..60).chain([1000, 4099, 100_000]) {
+            let n = Integer::from(n);
+            assert_eq!(seed_l_m_x(&n), closed_form_l_m_x(&n), "n={}", n);
+        }
+        assert_eq!(primes_up_to(30), vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29]);
+        // Across several segments
+        let limit = 3 * SEGMENT_ODDS + 12345;
+        let mut primes = Vec::new();
+        for_each_prime(limit, |p| primes.push(p));
+        assert_eq!(primes, primes_up_to(limit));
+        assert_eq!(legendre(100, 5), 24);
+    }
+
+    #[test]
+    fn test_n_past_u32() {
+        let n = Integer::from(u32::MAX) + 2;
+        assert_eq!(pow(&Integer::from(-1), &n), -1);
+        assert_eq!(pow(&Integer::from(-1), &Integer::from(&n + 1)), 1);
+        assert_eq!(range_product(&Integer::from(3), &Integer::from(6)), 360);
+
+        // One step of the recurrence from a made-up term n - 1 whose M makes the division exact
+        let mut _c = CalcPi::new(0_i128, 1_i128, Some("./testing")).unwrap();
+        _c.last_n = Integer::from(&n - 1);
+        _c._k = Integer::from(12 * &_c.last_n) - 6;
+        _c.last_l = Integer::from(545140134 * &_c.last_n) + 13591409;
+        _c.last_m = Integer::from(&n).pow(3);
+        _c.last_x = Integer::from(1);
+        _c.recursion_ready = true;
+        _c.calc_l_m_x(n.clone());
+        let k: Integer = Integer::from(12 * &n) - 6;
+        assert_eq!(_c.last_l, Integer::from(545140134 * &n) + 13591409);
+        assert_eq!(_c.last_m, Integer::from(&k).pow(3) - 16 * k);
+        assert_eq!(_c.last_x, -262537412640768000_i64);
+    }
+
     #[test]
     fn test_recursion_ready() {
         let test_path = Some("./testing");
-        let mut _c = CalcPi::new(0_i128, 1_i128, test_path);
+        let mut _c = CalcPi::new(0_i128, 1_i128, test_path).unwrap();
         _c.calc_l_m_x(Integer::from(0));
         _c.calc_l_m_x(Integer::from(1));
         assert!(_c.recursion_ready);
@@ -208,10 +1061,270 @@ mod tests {
         assert!(_c.recursion_ready);
     }
 
+    #[test]
+    fn test_binary_split_merge() {
+        let left = BinarySplit::new(0, 3).unwrap();
+        let right = BinarySplit::new(3, 7).unwrap();
+        assert_eq!(left.merge(right).unwrap(), BinarySplit::new(0, 7).unwrap());
+        assert_eq!(
+            BinarySplit::new(3, 7)
+                .unwrap()
+                .merge(BinarySplit::new(0, 3).unwrap()),
+            Err(BinarySplitError::RangesNotContiguous(7, 0))
+        );
+        assert_eq!(
+            BinarySplit::new(5, 5),
+            Err(BinarySplitError::EmptyRange(5, 5))
+        );
+    }
+
+    #[test]
+    fn test_binary_split_partial_sum() {
+        let mut expected = Rational::new();
+        for n in 0..6_u32 {
+            let m = Integer::factorial(6 * n).complete()
+                / (Integer::factorial(3 * n).complete() * Integer::factorial(n).complete().pow(3));
+            let l = Integer::from(545140134) * n + 13591409;
+            let x = Integer::from(-262537412640768000_i64).pow(n);
+            expected += Rational::from((m * l, x));
+        }
+        let whole = BinarySplit::new(0, 6).unwrap().partial_sum();
+        let head = BinarySplit::new(0, 4).unwrap().partial_sum();
+        let tail = BinarySplit::new(4, 6).unwrap().partial_sum();
+        assert_eq!(whole, expected);
+        assert_eq!(head + tail, expected);
+    }
+
+    #[test]
+    fn test_chudnovsky_sum_save_load() {
+        std::fs::create_dir_all("./testing").unwrap();
+        let sum = ChudnovskySum::from(&BinarySplit::new(3, 9).unwrap());
+        sum.save("./testing/sum.csv").unwrap();
+        assert_eq!(ChudnovskySum::load("./testing/sum.csv").unwrap(), sum);
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint() {
+        let checkpoint_path = "./testing/checkpoint_test.json";
+        std::fs::create_dir_all("./testing").unwrap();
+        Checkpoint::remove(checkpoint_path);
+
+        // Stop a job part way through by only computing its first 25 terms
+        let mut _c = CalcPi::new(0, 40, Some("./testing/checkpoint")).unwrap();
+        _c.set_checkpoint(checkpoint_path, 10);
+        _c.init_data_handler().unwrap();
+        for n in 0..25 {
+            _c.calc_l_m_x(Integer::from(n));
+            _c.write_most_recent_l_m_x().unwrap();
+            _c.checkpoint_if_due(n);
+        }
+
+        let mut _r = CalcPi::resume_from_checkpoint(checkpoint_path).unwrap();
+        assert_eq!(_r.n_next, 20);
+        assert_eq!(_r.last_n, Integer::from(19));
+        _r.set_checkpoint(checkpoint_path, 10);
+        _r.set_data_handler_archive_id(0, 9004);
+        _r.calc_pi_terms().unwrap();
+        assert!(!std::path::Path::new(checkpoint_path).exists());
+
+        let archive_path = std::path::Path::new("./pi_9004_0.tar.gz");
+        let sum = crate::reducer::read_archive(archive_path).unwrap();
+        std::fs::remove_file(archive_path).unwrap();
+        assert_eq!(sum, ChudnovskySum::from(&BinarySplit::new(0, 40).unwrap()));
+    }
+
+    #[test]
+    fn test_stale_checkpoint_is_discarded() {
+        let output_dir = "./testing/stale";
+        let checkpoint_path = format!("{}/checkpoint_9026_0.json", output_dir);
+        std::fs::create_dir_all(output_dir).unwrap();
+
+        // A checkpoint left at job 0's path by a job over other terms
+        let mut _c = CalcPi::new(100, 140, Some(output_dir)).unwrap();
+        _c.set_checkpoint(&checkpoint_path, 10);
+        _c.init_data_handler().unwrap();
+        for n in 100..115 {
+            _c.write_term(n).unwrap();
+            _c.checkpoint_if_due(n);
+        }
+        let _r = CalcPi::resume_from_checkpoint(&checkpoint_path).unwrap();
+        assert!(_r.is_job(100, 140, "csv", None));
+        assert!(!_r.is_job(0, 30, "csv", None));
+        assert!(!_r.is_job(100, 140, "bin", None));
+        assert!(!_r.is_job(100, 140, "csv", Some(10)));
+
+        let config = crate::config::WorkerConfig {
+            output_dir: output_dir.to_string(),
+            ..Default::default()
+        };
+        let scheduler = crate::scheduler::LocalScheduler::new(9026, 30, 30, 1);
+        let mut sh = crate::status_handler::StatusHandler::with_local_scheduler(config, scheduler);
+        sh.get_job().unwrap();
+        sh.dispatch_job().unwrap();
+        assert!(!std::path::Path::new(&checkpoint_path).exists());
+
+        let archive_path = std::path::Path::new("./pi_9026_0.tar.gz");
+        let sum = crate::reducer::read_archive(archive_path).unwrap();
+        std::fs::remove_file(archive_path).unwrap();
+        assert_eq!(sum, ChudnovskySum::from(&BinarySplit::new(0, 30).unwrap()));
+    }
+
+    #[test]
+    fn test_calc_pi_threads() {
+        let mut _c = CalcPi::new(0, 50, Some("./testing/threads")).unwrap();
+        _c.set_threads(3);
+        _c.set_data_handler_archive_id(0, 9005);
+        _c.calc_pi_terms().unwrap();
+
+        let archive_path = std::path::Path::new("./pi_9005_0.tar.gz");
+        let sum = crate::reducer::read_archive(archive_path).unwrap();
+        std::fs::remove_file(archive_path).unwrap();
+        assert_eq!(sum, ChudnovskySum::from(&BinarySplit::new(0, 50).unwrap()));
+    }
+
+    #[test]
+    fn test_resume_threaded_job() {
+        let base_path = "./testing/threads_resumed";
+        let checkpoint_path = format!("{}/checkpoint.json", base_path);
+        std::fs::remove_dir_all(base_path).unwrap_or(());
+        std::fs::create_dir_all(base_path).unwrap();
+
+        // Each run stops the job once every part has checkpointed, the last run
+        // splits the terms differently, and the final run finishes it
+        for (run, threads) in [3, 3, 2, 2].into_iter().enumerate() {
+            let mut _c = CalcPi::new(0, 1500, Some(base_path)).unwrap();
+            _c.set_threads(threads);
+            _c.set_checkpoint(&checkpoint_path, 20);
+            _c.set_data_handler_archive_id(0, 9027);
+            if run == 3 {
+                _c.calc_pi_terms().unwrap();
+                break;
+            }
+            let cancel = Arc::new(AtomicBool::new(false));
+            _c.set_cancel_flag(Arc::clone(&cancel));
+            let part_paths: Vec<String> = (0..threads)
+                .map(|k| format!("{}.part{}", checkpoint_path, k))
+                .collect();
+            let started = std::time::SystemTime::now();
+            let watcher = thread::spawn(move || {
+                let checkpointed = |path: &String| {
+                    std::fs::metadata(path)
+                        .and_then(|m| m.modified())
+                        .is_ok_and(|modified| modified > started)
+                };
+                while !part_paths.iter().all(checkpointed) {
+                    thread::sleep(Duration::from_millis(5));
+                }
+                cancel.store(true, Ordering::Relaxed);
+            });
+            assert_eq!(
+                _c.calc_pi_terms().unwrap_err().kind(),
+                ErrorKind::Interrupted
+            );
+            watcher.join().unwrap();
+        }
+
+        let archive_path = Path::new("./pi_9027_0.tar.gz");
+        let sum = crate::reducer::read_archive(archive_path).unwrap();
+        std::fs::remove_file(archive_path).unwrap();
+        assert_eq!(
+            sum,
+            ChudnovskySum::from(&BinarySplit::new(0, 1500).unwrap())
+        );
+        // Only the last run's directory is left, with the parts stitched into it
+        let dirs: Vec<_> = read_dir(base_path)
+            .unwrap()
+            .flatten()
+            .filter(|entry| entry.path().is_dir())
+            .collect();
+        assert_eq!(dirs.len(), 1);
+        assert_eq!(read_dir(dirs[0].path()).unwrap().count(), 2);
+        assert!(!Path::new(&format!("{}.part2", checkpoint_path)).exists());
+    }
+
+    #[test]
+    fn test_calc_pi_cancelled() {
+        for threads in [1, 2] {
+            let mut _c = CalcPi::new(0, 50, Some("./testing/cancelled")).unwrap();
+            _c.set_threads(threads);
+            _c.set_data_handler_archive_id(threads as u64, 9016);
+            _c.set_cancel_flag(Arc::new(AtomicBool::new(true)));
+            let error = _c.calc_pi_terms().unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::Interrupted);
+            assert!(!std::path::Path::new(&format!("./pi_9016_{}.tar.gz", threads)).exists());
+        }
+    }
+
+    #[test]
+    fn test_calc_pi_output_formats() {
+        for (id, file_type) in ["jsonl", "bin"].iter().enumerate() {
+            let mut _c =
+                CalcPi::with_output_format(0, 20, Some("./testing/formats"), file_type).unwrap();
+            _c.set_threads(2);
+            _c.set_data_handler_archive_id(id as u64, 9008);
+            _c.calc_pi_terms().unwrap();
+
+            let archive_path = format!("./pi_9008_{}.tar.gz", id);
+            let archive_path = std::path::Path::new(&archive_path);
+            let sum = crate::reducer::read_archive(archive_path).unwrap();
+            std::fs::remove_file(archive_path).unwrap();
+            assert_eq!(sum, ChudnovskySum::from(&BinarySplit::new(0, 20).unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_calc_pi_triples() {
+        for (id, (n_start, threads)) in [(0, 1), (7, 3)].into_iter().enumerate() {
+            let mut _c = CalcPi::new(n_start, 50, Some("./testing/triples")).unwrap();
+            _c.set_threads(threads);
+            _c.set_terms_per_triple(8);
+            _c.set_data_handler_archive_id(id as u64, 9025);
+            _c.calc_pi_terms().unwrap();
+
+            let archive_path = format!("./pi_9025_{}.tar.gz", id);
+            let archive_path = std::path::Path::new(&archive_path);
+            let sum = crate::reducer::read_archive(archive_path).unwrap();
+            std::fs::remove_file(archive_path).unwrap();
+            assert_eq!(
+                sum,
+                ChudnovskySum::from(&BinarySplit::new(n_start, 50).unwrap())
+            );
+        }
+    }
+
+    #[test]
+    fn test_resume_triples_from_checkpoint() {
+        let checkpoint_path = "./testing/checkpoint_triples.json";
+        std::fs::create_dir_all("./testing").unwrap();
+        Checkpoint::remove(checkpoint_path);
+
+        // The checkpoint at n=19 falls inside the chunk [18, 24)
+        let mut _c = CalcPi::new(0, 40, Some("./testing/checkpoint_triples")).unwrap();
+        _c.set_terms_per_triple(6);
+        _c.set_checkpoint(checkpoint_path, 10);
+        _c.init_data_handler().unwrap();
+        for n in 0..25 {
+            _c.write_term(n).unwrap();
+            _c.checkpoint_if_due(n);
+        }
+
+        let mut _r = CalcPi::resume_from_checkpoint(checkpoint_path).unwrap();
+        assert_eq!(_r.n_next, 18);
+        assert_eq!(_r.terms_per_triple, Some(6));
+        _r.set_checkpoint(checkpoint_path, 10);
+        _r.set_data_handler_archive_id(2, 9025);
+        _r.calc_pi_terms().unwrap();
+
+        let archive_path = std::path::Path::new("./pi_9025_2.tar.gz");
+        let sum = crate::reducer::read_archive(archive_path).unwrap();
+        std::fs::remove_file(archive_path).unwrap();
+        assert_eq!(sum, ChudnovskySum::from(&BinarySplit::new(0, 40).unwrap()));
+    }
+
     #[test]
     fn test_calc_pi() {
         let test_path = Some("./testing");
-        let mut _c = CalcPi::new(0, 1000, test_path);
+        let mut _c = CalcPi::new(0, 1000, test_path).unwrap();
         _c.calc_pi_terms().unwrap();
     }
 }
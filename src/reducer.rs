@@ -0,0 +1,710 @@
+use std::fmt;
+use std::fs::{read_dir, File};
+use std::io::Read;
+use std::path::Path;
+
+use flate2::read::{GzDecoder, MultiGzDecoder};
+use rug::{Integer, Rational};
+use tar::Archive;
+
+use crate::artifact_store::sha256_reader;
+use crate::manifest::{Manifest, MANIFEST_NAME};
+use crate::output_format::{output_format_from_name, OutputFormat};
+use crate::pi_math::{BinarySplit, BinarySplitError, ChudnovskySum};
+
+/// -640320^3, the ratio between the X values of consecutive terms.
+const X_RATIO: i128 = -262537412640768000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReducerError {
+    ReadError(String),
+    InvalidArchive(String, String),
+    NoArchivesFound(String),
+    NoArchives,
+    GapBetweenRanges(i128, i128),
+    OverlappingRanges(i128, i128),
+}
+
+impl fmt::Display for ReducerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReducerError::ReadError(_s) => write!(f, "Could not read archive: {}", _s),
+            ReducerError::InvalidArchive(_a, _s) => {
+                write!(f, "The archive {} is invalid: {}", _a, _s)
+            }
+            ReducerError::NoArchivesFound(_d) => write!(f, "No archives found in {}", _d),
+            ReducerError::NoArchives => write!(f, "No archives to merge"),
+            ReducerError::GapBetweenRanges(_e, _s) => write!(
+                f,
+                "Terms {} to {} are missing from the archives",
+                _e,
+                _s - 1
+            ),
+            ReducerError::OverlappingRanges(_e, _s) => write!(
+                f,
+                "A range starting at {} overlaps a range ending at {}",
+                _s, _e
+            ),
+        }
+    }
+}
+
+impl From<std::io::Error> for ReducerError {
+    fn from(e: std::io::Error) -> Self {
+        ReducerError::ReadError(e.to_string())
+    }
+}
+
+/// Merges every `pi_{batch}_{id}.tar.gz` archive in `archive_dir` into a single sum.
+pub fn reduce_directory(archive_dir: &str) -> Result<ChudnovskySum, ReducerError> {
+    let mut archive_paths = vec![];
+    for entry in read_dir(archive_dir)? {
+        let path = entry?.path();
+        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+        if file_name.starts_with("pi_") && file_name.ends_with(".tar.gz") {
+            archive_paths.push(path);
+        }
+    }
+    if archive_paths.is_empty() {
+        return Err(ReducerError::NoArchivesFound(archive_dir.to_string()));
+    }
+    reduce_archives(&archive_paths)
+}
+
+/// Merges the given archives into a single sum.
+///
+/// Triples from every archive are merged with each other before anything is
+/// turned into a sum, so archives of triples that start at n=0 only ever
+/// divide once, with no seeding.
+pub fn reduce_archives<P: AsRef<Path>>(archive_paths: &[P]) -> Result<ChudnovskySum, ReducerError> {
+    let mut parts = vec![];
+    for path in archive_paths.iter() {
+        parts.extend(read_archive_parts(path.as_ref())?);
+    }
+    into_sum(merge_parts(parts)?)
+}
+
+/// Sums every term or triple row stored in one archive.
+pub fn read_archive(path: &Path) -> Result<ChudnovskySum, ReducerError> {
+    into_sum(read_archive_parts(path)?)
+}
+
+/// Reads one archive into contiguous parts, merging the triples of its data
+/// files with each other but leaving them as triples.
+fn read_archive_parts(path: &Path) -> Result<Vec<Part>, ReducerError> {
+    let archive_name = path.display().to_string();
+    let mut parts = vec![];
+    for_each_data_file(path, |_, format, reader| {
+        if let Some(part) = sum_data_rows(format, reader)? {
+            parts.push(part);
+        }
+        Ok(())
+    })?;
+    let parts = merge_parts(parts)
+        .map_err(|e| ReducerError::InvalidArchive(archive_name.clone(), e.to_string()))?;
+    let (Some(first), Some(last)) = (parts.first(), parts.last()) else {
+        return Err(ReducerError::InvalidArchive(
+            archive_name,
+            "no term or triple rows".to_string(),
+        ));
+    };
+    let (n_start, n_end) = (first.n_start(), last.n_end());
+    if let Some(manifest) = read_manifest(path)? {
+        if (n_start, n_end) != (manifest.n_start, manifest.n_end) {
+            return Err(ReducerError::InvalidArchive(
+                archive_name,
+                format!(
+                    "the rows hold terms {} to {} but the manifest lists {} to {}",
+                    n_start,
+                    n_end - 1,
+                    manifest.n_start,
+                    manifest.n_end - 1
+                ),
+            ));
+        }
+    }
+    Ok(parts)
+}
+
+/// Reads the manifest stored at the start of an archive, or returns `None` for
+/// archives written before manifests were added.
+pub fn read_manifest(path: &Path) -> Result<Option<Manifest>, ReducerError> {
+    let mut archive = Archive::new(GzDecoder::new(File::open(path)?));
+    let mut entry = match archive.entries()?.next() {
+        Some(entry) => entry?,
+        None => return Ok(None),
+    };
+    if entry.path()?.file_name() != Some(MANIFEST_NAME.as_ref()) {
+        return Ok(None);
+    }
+    serde_json::from_reader(&mut entry)
+        .map(Some)
+        .map_err(|e| ReducerError::InvalidArchive(path.display().to_string(), e.to_string()))
+}
+
+/// Checks the size and SHA-256 of every data file against the archive's
+/// manifest, without decoding any rows, and returns the manifest.
+pub fn verify_archive(path: &Path) -> Result<Manifest, ReducerError> {
+    let archive_name = path.display().to_string();
+    let manifest = read_manifest(path)?.ok_or_else(|| {
+        ReducerError::InvalidArchive(archive_name.clone(), "no manifest".to_string())
+    })?;
+    let mut seen = vec![];
+    for_each_data_entry(path, |file_name, _, _, reader| {
+        let listed = manifest
+            .file(file_name)
+            .ok_or("not listed in the manifest")?;
+        let (sha256, size) = sha256_reader(reader).map_err(|e| e.to_string())?;
+        if size != listed.size {
+            return Err(format!(
+                "{} bytes but the manifest lists {}",
+                size, listed.size
+            ));
+        }
+        if sha256 != listed.sha256 {
+            return Err(format!(
+                "SHA-256 {} but the manifest lists {}",
+                sha256, listed.sha256
+            ));
+        }
+        seen.push(file_name.to_string());
+        Ok(())
+    })?;
+    if let Some(missing) = manifest.files.iter().find(|f| !seen.contains(&f.name)) {
+        return Err(ReducerError::InvalidArchive(
+            archive_name,
+            format!("{} is listed in the manifest but missing", missing.name),
+        ));
+    }
+    Ok(manifest)
+}
+
+/// What one data file in an archive holds.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DataFileSummary {
+    pub file_name: String,
+    pub file_type: String,
+    pub rows: u64,
+    /// The rows are binary splitting triples rather than terms.
+    pub triples: bool,
+    /// The first and last n covered by the file, if it has any rows.
+    pub n_range: Option<(i128, i128)>,
+}
+
+/// Lists the data files of an archive, in file number order, without summing them.
+pub fn inspect_archive(path: &Path) -> Result<Vec<DataFileSummary>, ReducerError> {
+    let mut summaries = vec![];
+    for_each_data_file(path, |file_name, format, reader| {
+        let mut summary = DataFileSummary {
+            file_name: file_name.to_string(),
+            file_type: format.file_type().to_string(),
+            rows: 0,
+            triples: false,
+            n_range: None,
+        };
+        format.decode_rows(reader, &mut |row| {
+            let row = data_row(row)?;
+            let (first_n, last_n) = row.n_range();
+            summary.rows += 1;
+            summary.triples = matches!(row, DataRow::Triple(_));
+            summary.n_range = match summary.n_range {
+                Some((first, _)) => Some((first, last_n)),
+                None => Some((first_n, last_n)),
+            };
+            Ok(())
+        })?;
+        summaries.push(summary);
+        Ok(())
+    })?;
+    summaries.sort_by_key(|s| {
+        s.file_name
+            .trim_start_matches("data")
+            .split('.')
+            .next()
+            .and_then(|number| number.parse::<u64>().ok())
+    });
+    Ok(summaries)
+}
+
+/// Calls `f` with the name, format and contents of every `data{N}.{file_type}`
+/// file in an archive, in the order they are stored, decompressing
+/// `data{N}.{file_type}.gz` files on the way.
+pub fn for_each_data_file<F>(path: &Path, mut f: F) -> Result<(), ReducerError>
+where
+    F: FnMut(&str, &dyn OutputFormat, &mut dyn Read) -> Result<(), String>,
+{
+    for_each_data_entry(path, |file_name, format, compressed, entry| {
+        if compressed {
+            f(file_name, format, &mut MultiGzDecoder::new(entry))
+        } else {
+            f(file_name, format, entry)
+        }
+    })
+}
+
+/// Like `for_each_data_file`, but passes the bytes as stored along with
+/// whether they are gzip compressed.
+fn for_each_data_entry<F>(path: &Path, mut f: F) -> Result<(), ReducerError>
+where
+    F: FnMut(&str, &dyn OutputFormat, bool, &mut dyn Read) -> Result<(), String>,
+{
+    let archive_name = path.display().to_string();
+    let mut archive = Archive::new(GzDecoder::new(File::open(path)?));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let file_name = entry
+            .path()?
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let (stem, compressed) = match file_name.strip_suffix(".gz") {
+            Some(stem) => (stem, true),
+            None => (file_name.as_str(), false),
+        };
+        let format = match stem.rsplit_once('.') {
+            Some((stem, file_type)) if stem.starts_with("data") => {
+                match output_format_from_name(file_type) {
+                    Some(format) => format,
+                    None => continue,
+                }
+            }
+            _ => continue,
+        };
+        f(&file_name, format.as_ref(), compressed, &mut entry).map_err(|e| {
+            ReducerError::InvalidArchive(format!("{}/{}", archive_name, file_name), e)
+        })?;
+    }
+    Ok(())
+}
+
+/// A decoded data row, either one term or the triple of a chunk of terms.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DataRow {
+    /// n, l, m and x.
+    Term(i128, Integer, Integer, Integer),
+    Triple(BinarySplit),
+}
+
+impl DataRow {
+    /// The first and last n the row covers.
+    pub fn n_range(&self) -> (i128, i128) {
+        match self {
+            DataRow::Term(n, _, _, _) => (*n, *n),
+            DataRow::Triple(split) => (split.n_start, split.n_end - 1),
+        }
+    }
+}
+
+/// Reads a decoded row as an `n,l,m,x` term or an `n_start,n_end,p,q,t` triple.
+pub fn data_row(row: Vec<Integer>) -> Result<DataRow, String> {
+    if row.len() == 5 {
+        return triple_row(row).map(DataRow::Triple);
+    }
+    let (n, l, m, x) = term_row(row)?;
+    Ok(DataRow::Term(n, l, m, x))
+}
+
+/// Splits a decoded row into n, l, m and x.
+pub fn term_row(row: Vec<Integer>) -> Result<(i128, Integer, Integer, Integer), String> {
+    let [n, l, m, x]: [Integer; 4] = row
+        .try_into()
+        .map_err(|_| "row does not have 4 values".to_string())?;
+    let n = n
+        .to_i128()
+        .ok_or_else(|| format!("n={} is out of range", n))?;
+    Ok((n, l, m, x))
+}
+
+/// Splits a decoded row into the binary splitting triple of terms n_start..n_end.
+pub fn triple_row(row: Vec<Integer>) -> Result<BinarySplit, String> {
+    let [n_start, n_end, p, q, t]: [Integer; 5] = row
+        .try_into()
+        .map_err(|_| "row does not have 5 values".to_string())?;
+    let to_i128 = |n: Integer| {
+        n.to_i128()
+            .ok_or_else(|| format!("n={} is out of range", n))
+    };
+    let (n_start, n_end) = (to_i128(n_start)?, to_i128(n_end)?);
+    if n_start < 0 || n_end <= n_start {
+        return Err(BinarySplitError::EmptyRange(n_start, n_end).to_string());
+    }
+    // Every sum built from the triple divides by q
+    if q == 0 {
+        return Err(format!("q is 0 for terms {}..{}", n_start, n_end));
+    }
+    Ok(BinarySplit {
+        n_start,
+        n_end,
+        p,
+        q,
+        t,
+    })
+}
+
+/// Sorts the sums by range and merges them, rejecting gaps and overlaps.
+pub fn merge_sums(mut sums: Vec<ChudnovskySum>) -> Result<ChudnovskySum, ReducerError> {
+    sums.sort_by_key(|s| s.n_start);
+    let mut sums = sums.into_iter();
+    let mut merged = sums.next().ok_or(ReducerError::NoArchives)?;
+    for sum in sums {
+        merged = merged.merge(sum).map_err(range_error)?;
+    }
+    Ok(merged)
+}
+
+fn range_error(e: BinarySplitError) -> ReducerError {
+    match e {
+        BinarySplitError::RangesNotContiguous(end, start) if start > end => {
+            ReducerError::GapBetweenRanges(end, start)
+        }
+        BinarySplitError::RangesNotContiguous(end, start) => {
+            ReducerError::OverlappingRanges(end, start)
+        }
+        e => ReducerError::ReadError(e.to_string()),
+    }
+}
+
+/// The rows of a run of terms, summed for per-term rows or still a triple
+/// for triple rows, which is only turned into a sum once nothing else can be
+/// merged into it.
+#[derive(Debug, PartialEq, Eq)]
+enum Part {
+    Sum(ChudnovskySum),
+    Split(BinarySplit),
+}
+
+impl Part {
+    fn n_start(&self) -> i128 {
+        match self {
+            Part::Sum(sum) => sum.n_start,
+            Part::Split(split) => split.n_start,
+        }
+    }
+
+    fn n_end(&self) -> i128 {
+        match self {
+            Part::Sum(sum) => sum.n_end,
+            Part::Split(split) => split.n_end,
+        }
+    }
+}
+
+/// Sorts the parts by range, rejecting gaps and overlaps, and merges every
+/// run of neighbouring triples into one triple and every run of sums into one sum.
+fn merge_parts(mut parts: Vec<Part>) -> Result<Vec<Part>, ReducerError> {
+    parts.sort_by_key(Part::n_start);
+    for pair in parts.windows(2) {
+        let (end, start) = (pair[0].n_end(), pair[1].n_start());
+        if end != start {
+            return Err(range_error(BinarySplitError::RangesNotContiguous(
+                end, start,
+            )));
+        }
+    }
+    let mut merged = vec![];
+    let mut splits = vec![];
+    for part in parts {
+        let sum = match part {
+            Part::Split(split) => {
+                splits.push(split);
+                continue;
+            }
+            Part::Sum(sum) => sum,
+        };
+        if !splits.is_empty() {
+            let split = merge_splits(std::mem::take(&mut splits)).map_err(range_error)?;
+            merged.push(Part::Split(split));
+        }
+        // The ranges were checked above, so a sum just extends the one before it
+        match merged.last_mut() {
+            Some(Part::Sum(previous)) => {
+                previous.n_end = sum.n_end;
+                previous.sum += sum.sum;
+            }
+            _ => merged.push(Part::Sum(sum)),
+        }
+    }
+    if !splits.is_empty() {
+        merged.push(Part::Split(merge_splits(splits).map_err(range_error)?));
+    }
+    Ok(merged)
+}
+
+/// Turns merged parts into a single sum, seeding each triple from its start.
+fn into_sum(parts: Vec<Part>) -> Result<ChudnovskySum, ReducerError> {
+    let sums = parts
+        .into_iter()
+        .map(|part| match part {
+            Part::Sum(sum) => sum,
+            Part::Split(split) => ChudnovskySum::from(&split),
+        })
+        .collect();
+    merge_sums(sums)
+}
+
+/// Sums the rows of one data file, or returns `None` if it only has a header.
+///
+/// `n,l,m,x` rows are accumulated with Horner's rule over the constant X
+/// ratio, so only the last X value is ever divided by. Triple rows are merged
+/// into one triple for the whole file, which is left as a triple.
+fn sum_data_rows(format: &dyn OutputFormat, reader: &mut dyn Read) -> Result<Option<Part>, String> {
+    let mut n_start = None;
+    let mut n_end: i128 = 0;
+    let mut last_x = Integer::new();
+    let mut acc = Integer::new();
+    let mut triples = vec![];
+    format.decode_rows(reader, &mut |row| {
+        let row = data_row(row)?;
+        let (first_n, last_n) = row.n_range();
+        if n_start.is_some() && first_n != n_end {
+            return Err(format!("n jumps from {} to {}", n_end - 1, first_n));
+        }
+        n_start.get_or_insert(first_n);
+        n_end = last_n + 1;
+
+        let mixed = match row {
+            DataRow::Term(_, l, m, x) => {
+                acc *= X_RATIO;
+                acc += m * l;
+                last_x = x;
+                !triples.is_empty()
+            }
+            DataRow::Triple(split) => {
+                triples.push(split);
+                // X is never 0, so it is only set once a term row was read
+                last_x != 0
+            }
+        };
+        if mixed {
+            return Err("the file mixes term and triple rows".to_string());
+        }
+        Ok(())
+    })?;
+    let Some(n_start) = n_start else {
+        return Ok(None);
+    };
+    if triples.is_empty() {
+        return Ok(Some(Part::Sum(ChudnovskySum {
+            n_start,
+            n_end,
+            sum: Rational::from((acc, last_x)),
+        })));
+    }
+    let split = merge_splits(triples).map_err(|e| e.to_string())?;
+    Ok(Some(Part::Split(split)))
+}
+
+/// Merges contiguous triples, pairing neighbours so the operands stay balanced.
+fn merge_splits(mut splits: Vec<BinarySplit>) -> Result<BinarySplit, BinarySplitError> {
+    while splits.len() > 1 {
+        let mut pairs = splits.into_iter();
+        let mut merged = vec![];
+        while let Some(left) = pairs.next() {
+            merged.push(match pairs.next() {
+                Some(right) => left.merge(right)?,
+                None => left,
+            });
+        }
+        splits = merged;
+    }
+    Ok(splits.pop().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pi_math::{BinarySplit, CalcPi};
+    use std::fs::{create_dir_all, remove_dir_all, rename};
+
+    fn write_archive(n_start: i128, n_end: i128, id: u64, archive_dir: &str) {
+        let mut calc_pi = CalcPi::new(n_start, n_end, Some("./testing/reducer")).unwrap();
+        calc_pi.set_data_handler_archive_id(id, 9003);
+        calc_pi.calc_pi_terms().unwrap();
+        let archive_name = format!("pi_9003_{}.tar.gz", id);
+        rename(&archive_name, format!("{}/{}", archive_dir, archive_name)).unwrap();
+    }
+
+    #[test]
+    fn test_reduce_directory() {
+        let archive_dir = "./testing/reducer/contiguous";
+        remove_dir_all(archive_dir).unwrap_or(());
+        create_dir_all(archive_dir).unwrap();
+        write_archive(0, 15, 0, archive_dir);
+        write_archive(15, 40, 1, archive_dir);
+
+        let merged = reduce_directory(archive_dir).unwrap();
+        assert_eq!(
+            merged,
+            ChudnovskySum::from(&BinarySplit::new(0, 40).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_merge_triples_across_archives() {
+        let archive_dir = "./testing/reducer/triples";
+        remove_dir_all(archive_dir).unwrap_or(());
+        create_dir_all(archive_dir).unwrap();
+        for (n_start, n_end, id) in [(0, 20, 8), (20, 45, 9)] {
+            let mut calc_pi = CalcPi::new(n_start, n_end, Some("./testing/reducer")).unwrap();
+            calc_pi.set_terms_per_triple(7);
+            calc_pi.set_data_handler_archive_id(id, 9003);
+            calc_pi.calc_pi_terms().unwrap();
+            let archive_name = format!("pi_9003_{}.tar.gz", id);
+            rename(&archive_name, format!("{}/{}", archive_dir, archive_name)).unwrap();
+        }
+        write_archive(45, 50, 10, archive_dir);
+        let archive_path = |id: u64| format!("{}/pi_9003_{}.tar.gz", archive_dir, id);
+
+        // Both archives of triples merge into one triple from n=0, and the
+        // per-term archive after them stays a sum
+        let mut parts = vec![];
+        for id in [10, 9, 8] {
+            parts.extend(read_archive_parts(Path::new(&archive_path(id))).unwrap());
+        }
+        let parts = merge_parts(parts).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0], Part::Split(BinarySplit::new(0, 45).unwrap()));
+        assert_eq!(
+            into_sum(parts).unwrap(),
+            ChudnovskySum::from(&BinarySplit::new(0, 50).unwrap())
+        );
+        assert_eq!(
+            reduce_directory(archive_dir).unwrap(),
+            ChudnovskySum::from(&BinarySplit::new(0, 50).unwrap())
+        );
+
+        assert_eq!(
+            reduce_archives(&[archive_path(8), archive_path(10)]),
+            Err(ReducerError::GapBetweenRanges(20, 45))
+        );
+    }
+
+    #[test]
+    fn test_reduce_directory_with_gap() {
+        let archive_dir = "./testing/reducer/gap";
+        remove_dir_all(archive_dir).unwrap_or(());
+        create_dir_all(archive_dir).unwrap();
+        write_archive(0, 15, 2, archive_dir);
+        write_archive(16, 20, 3, archive_dir);
+
+        assert_eq!(
+            reduce_directory(archive_dir),
+            Err(ReducerError::GapBetweenRanges(15, 16))
+        );
+    }
+
+    #[test]
+    fn test_inspect_archive() {
+        let archive_dir = "./testing/reducer/inspect";
+        remove_dir_all(archive_dir).unwrap_or(());
+        create_dir_all(archive_dir).unwrap();
+        write_archive(5, 12, 4, archive_dir);
+
+        let summaries =
+            inspect_archive(Path::new(&format!("{}/pi_9003_4.tar.gz", archive_dir))).unwrap();
+        assert_eq!(
+            summaries,
+            vec![DataFileSummary {
+                file_name: "data0.csv.gz".to_string(),
+                file_type: "csv".to_string(),
+                rows: 7,
+                triples: false,
+                n_range: Some((5, 11)),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_inspect_triple_archive() {
+        let mut calc_pi = CalcPi::new(5, 30, Some("./testing/reducer")).unwrap();
+        calc_pi.set_terms_per_triple(10);
+        calc_pi.set_data_handler_archive_id(7, 9003);
+        calc_pi.calc_pi_terms().unwrap();
+        let archive_path = Path::new("./pi_9003_7.tar.gz");
+
+        let summaries = inspect_archive(archive_path).unwrap();
+        let sum = read_archive(archive_path).unwrap();
+        std::fs::remove_file(archive_path).unwrap();
+        assert_eq!(
+            summaries,
+            vec![DataFileSummary {
+                file_name: "data0.csv.gz".to_string(),
+                file_type: "csv".to_string(),
+                rows: 3,
+                triples: true,
+                n_range: Some((5, 29)),
+            }]
+        );
+        assert_eq!(sum, ChudnovskySum::from(&BinarySplit::new(5, 30).unwrap()));
+    }
+
+    #[test]
+    fn test_triple_row_with_zero_q() {
+        let row = |q: u32| [5, 15, 1, q, 1].map(Integer::from).to_vec();
+        assert!(triple_row(row(1)).is_ok());
+        assert_eq!(
+            triple_row(row(0)).err(),
+            Some("q is 0 for terms 5..15".to_string())
+        );
+    }
+
+    #[test]
+    fn test_verify_archive() {
+        let archive_dir = "./testing/reducer/manifest";
+        remove_dir_all(archive_dir).unwrap_or(());
+        create_dir_all(archive_dir).unwrap();
+        write_archive(3, 10, 5, archive_dir);
+        let archive_path = format!("{}/pi_9003_5.tar.gz", archive_dir);
+
+        let manifest = verify_archive(Path::new(&archive_path)).unwrap();
+        assert_eq!((manifest.job_id, manifest.batch_id), (Some(5), Some(9003)));
+        assert_eq!((manifest.n_start, manifest.n_end), (3, 10));
+        assert_eq!(manifest.rows(), 7);
+        assert_eq!(manifest.files[0].name, "data0.csv.gz");
+        assert_eq!(manifest.crate_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(read_manifest(Path::new(&archive_path)), Ok(Some(manifest)));
+
+        // An archive whose data file no longer matches its manifest
+        let tampered_path = format!("{}/pi_9003_6.tar.gz", archive_dir);
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            File::create(&tampered_path).unwrap(),
+            flate2::Compression::default(),
+        ));
+        let mut archive = Archive::new(GzDecoder::new(File::open(&archive_path).unwrap()));
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let mut data = vec![];
+            entry.read_to_end(&mut data).unwrap();
+            if entry.path().unwrap().ends_with("data0.csv.gz") {
+                data.extend_from_slice(b"10,1,1,1,\n");
+            }
+            let mut header = entry.header().clone();
+            header.set_size(data.len() as u64);
+            let entry_path = entry.path().unwrap().to_path_buf();
+            builder
+                .append_data(&mut header, entry_path, data.as_slice())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+        assert!(matches!(
+            verify_archive(Path::new(&tampered_path)),
+            Err(ReducerError::InvalidArchive(name, _)) if name.ends_with("data0.csv.gz")
+        ));
+    }
+
+    #[test]
+    fn test_reduce_no_archives() {
+        let no_paths: [&str; 0] = [];
+        assert_eq!(reduce_archives(&no_paths), Err(ReducerError::NoArchives));
+    }
+
+    #[test]
+    fn test_merge_overlapping_sums() {
+        let a = ChudnovskySum::from(&BinarySplit::new(0, 10).unwrap());
+        let b = ChudnovskySum::from(&BinarySplit::new(8, 12).unwrap());
+        assert_eq!(
+            merge_sums(vec![b, a]),
+            Err(ReducerError::OverlappingRanges(10, 8))
+        );
+    }
+}
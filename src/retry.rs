@@ -0,0 +1,175 @@
+use std::fmt;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::config::WorkerConfig;
+
+/// How a failed attempt should be treated.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RetryError<E> {
+    /// Worth trying again, such as a timeout or a 503.
+    Retryable(E),
+    /// Will fail the same way every time, such as a 404.
+    Fatal(E),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StatusClass {
+    Success,
+    Retryable,
+    Fatal,
+}
+
+/// Sorts HTTP statuses into success, worth retrying, and not worth retrying.
+pub fn classify_status(status: u16) -> StatusClass {
+    match status {
+        200..=299 => StatusClass::Success,
+        408 | 425 | 429 => StatusClass::Retryable,
+        500..=599 => StatusClass::Retryable,
+        _ => StatusClass::Fatal,
+    }
+}
+
+/// Exponential backoff with jitter, bounded by a number of retries and by the
+/// total time spent.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub max_elapsed: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &WorkerConfig) -> Self {
+        RetryPolicy {
+            max_retries: config.retry_count,
+            initial_delay: Duration::from_millis(config.retry_delay_ms),
+            max_delay: Duration::from_millis(config.retry_max_delay_ms),
+            max_elapsed: Duration::from_secs(config.retry_max_elapsed_secs),
+        }
+    }
+
+    /// How long to wait before retry number `retry` (starting at 0).
+    ///
+    /// The delay doubles every retry up to `max_delay`, and half of it is
+    /// random so that workers which failed together do not retry together.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let backoff = self.initial_delay.as_secs_f64() * 2_f64.powi(retry.min(31) as i32);
+        let capped = backoff.min(self.max_delay.as_secs_f64());
+        Duration::from_secs_f64(capped / 2.0 + fastrand::f64() * capped / 2.0)
+    }
+
+    /// Runs `attempt` until it succeeds, fails fatally, or the policy gives up,
+    /// returning the last error in the last two cases.
+    pub async fn retry<T, E, F, Fut>(&self, what: &str, mut attempt: F) -> Result<T, E>
+    where
+        E: fmt::Display,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, RetryError<E>>>,
+    {
+        let start = Instant::now();
+        let mut retries = 0;
+        loop {
+            match attempt().await {
+                Ok(value) => return Ok(value),
+                Err(RetryError::Fatal(e)) => return Err(e),
+                Err(RetryError::Retryable(e)) => {
+                    let delay = self.delay(retries);
+                    if retries >= self.max_retries || start.elapsed() + delay > self.max_elapsed {
+                        return Err(e);
+                    }
+                    println!("{} failed, retrying in {:?}: {}", what, delay, e);
+                    tokio::time::sleep(delay).await;
+                    retries += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(max_retries: u32, max_elapsed: Duration) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(4),
+            max_elapsed,
+        }
+    }
+
+    #[test]
+    fn test_delay_backs_off_with_jitter() {
+        let policy = policy(10, Duration::from_secs(1));
+        for _ in 0..100 {
+            let first = policy.delay(0);
+            assert!(first >= Duration::from_micros(500) && first <= Duration::from_millis(1));
+            let capped = policy.delay(20);
+            assert!(capped >= Duration::from_millis(2) && capped <= Duration::from_millis(4));
+        }
+    }
+
+    #[test]
+    fn test_classify_status() {
+        assert_eq!(classify_status(204), StatusClass::Success);
+        assert_eq!(classify_status(503), StatusClass::Retryable);
+        assert_eq!(classify_status(429), StatusClass::Retryable);
+        assert_eq!(classify_status(404), StatusClass::Fatal);
+        assert_eq!(classify_status(409), StatusClass::Fatal);
+    }
+
+    #[tokio::test]
+    async fn test_retry_until_success() {
+        let mut attempts = 0;
+        let result: Result<i32, String> = policy(5, Duration::from_secs(1))
+            .retry("test", || {
+                attempts += 1;
+                let attempt = attempts;
+                async move {
+                    match attempt {
+                        3 => Ok(7),
+                        _ => Err(RetryError::Retryable("timeout".to_string())),
+                    }
+                }
+            })
+            .await;
+        assert_eq!(result, Ok(7));
+        assert_eq!(attempts, 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_gives_up() {
+        let mut attempts = 0;
+        let result: Result<(), String> = policy(2, Duration::from_secs(1))
+            .retry("test", || {
+                attempts += 1;
+                async { Err(RetryError::Retryable("HTTP 503".to_string())) }
+            })
+            .await;
+        assert_eq!(result, Err("HTTP 503".to_string()));
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        let result: Result<(), String> = policy(5, Duration::from_secs(1))
+            .retry("test", || {
+                attempts += 1;
+                async { Err(RetryError::Fatal("HTTP 404".to_string())) }
+            })
+            .await;
+        assert_eq!(result, Err("HTTP 404".to_string()));
+        assert_eq!(attempts, 1);
+
+        let mut attempts = 0;
+        let result: Result<(), String> = policy(100, Duration::ZERO)
+            .retry("test", || {
+                attempts += 1;
+                async { Err(RetryError::Retryable("timeout".to_string())) }
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::VecDeque;
+
+use crate::pi_digits::{terms_needed, PiDigitsError};
+use crate::status_handler::{JobArgs, JobBatch, JobInfo};
+
+/// Hands out the jobs of one batch on this machine, in the same shape the API
+/// returns them.
+pub struct LocalScheduler {
+    batch_id: u64,
+    job_count: usize,
+    jobs: VecDeque<JobInfo>,
+}
+
+impl LocalScheduler {
+    /// Splits terms [0, n_end) into jobs of at most `terms_per_job` terms,
+    /// each run on `threads` threads.
+    pub fn new(batch_id: u64, n_end: i128, terms_per_job: i128, threads: usize) -> Self {
+        let terms_per_job = terms_per_job.max(1);
+        let jobs: VecDeque<JobInfo> = (0..n_end)
+            .step_by(terms_per_job as usize)
+            .enumerate()
+            .map(|(id, start_n)| JobInfo {
+                id: id as u64,
+                job_batch: JobBatch {
+                    cpu_needed: threads.max(1) as f32,
+                    ram_needed: 0.0,
+                    id: batch_id,
+                },
+                job_args: JobArgs {
+                    start_n,
+                    end_n: (start_n + terms_per_job).min(n_end),
+                    status_update_interval: (terms_per_job / 10).max(1),
+                    output_format: None,
+                    terms_per_triple: None,
+                },
+            })
+            .collect();
+        LocalScheduler {
+            batch_id,
+            job_count: jobs.len(),
+            jobs,
+        }
+    }
+
+    /// Enough jobs to compute `digits` digits of pi in `radix`.
+    pub fn for_digits(
+        batch_id: u64,
+        digits: u32,
+        radix: i32,
+        terms_per_job: i128,
+        threads: usize,
+    ) -> Result<Self, PiDigitsError> {
+        Ok(LocalScheduler::new(
+            batch_id,
+            terms_needed(digits, radix)?,
+            terms_per_job,
+            threads,
+        ))
+    }
+
+    pub fn next_job(&mut self) -> Option<JobInfo> {
+        self.jobs.pop_front()
+    }
+
+    /// Names of the archives the batch's jobs write, `pi_{batch}_{id}.tar.gz`.
+    pub fn archive_names(&self) -> Vec<String> {
+        (0..self.job_count)
+            .map(|id| format!("pi_{}_{}.tar.gz", self.batch_id, id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WorkerConfig;
+    use crate::pi_digits::{pi_digits, reference_pi_digits};
+    use crate::reducer::reduce_archives;
+    use crate::status_handler::{StatusHandler, StatusHandlerError};
+
+    #[test]
+    fn test_split_into_jobs() {
+        let mut scheduler = LocalScheduler::new(7, 25, 10, 2);
+        let ranges: Vec<(u64, i128, i128)> = std::iter::from_fn(|| scheduler.next_job())
+            .map(|job| (job.id, job.job_args.start_n, job.job_args.end_n))
+            .collect();
+        assert_eq!(ranges, vec![(0, 0, 10), (1, 10, 20), (2, 20, 25)]);
+        assert_eq!(
+            scheduler.archive_names(),
+            vec!["pi_7_0.tar.gz", "pi_7_1.tar.gz", "pi_7_2.tar.gz"]
+        );
+    }
+
+    #[test]
+    fn test_offline_batch() {
+        let scheduler = LocalScheduler::for_digits(9011, 60, 10, 3, 1).unwrap();
+        let config = WorkerConfig {
+            output_dir: "./testing/scheduler".to_string(),
+            ..WorkerConfig::default()
+        };
+        std::fs::create_dir_all(&config.output_dir).unwrap();
+        let archive_names = scheduler.archive_names();
+        let mut sh = StatusHandler::with_local_scheduler(config, scheduler);
+        loop {
+            match sh.get_job() {
+                Ok(()) => sh.dispatch_job().unwrap(),
+                Err(StatusHandlerError::NoJobsLeft) => break,
+                Err(e) => panic!("{:?}", e),
+            }
+        }
+
+        let sum = reduce_archives(&archive_names).unwrap();
+        for name in archive_names {
+            std::fs::remove_file(name).unwrap();
+        }
+        assert_eq!(
+            pi_digits(&sum, 60, 10).unwrap(),
+            reference_pi_digits(60, 10).unwrap()
+        );
+    }
+}
@@ -0,0 +1,113 @@
+//! Handles SIGTERM, which HTCondor sends before evicting a job, and Ctrl-C.
+//!
+//! The handlers are installed once, on a thread of their own, and stay for the
+//! rest of the process. A signal that arrives while a job is listening asks the
+//! job to stop at its next term. One that arrives with nothing listening, such
+//! as between jobs or while merging, or a second signal, exits the process
+//! straight away, as it would have without the handlers.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+use tokio::sync::watch;
+
+static SIGNALLED: OnceLock<watch::Sender<bool>> = OnceLock::new();
+static LISTENERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Installs the handlers, returning once they are in place. Later calls do nothing.
+pub fn install() {
+    SIGNALLED.get_or_init(|| {
+        let (signalled, _) = watch::channel(false);
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+        let sender = signalled.clone();
+        std::thread::spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    println!("Could not handle shutdown signals: {}", e);
+                    ready_tx.send(()).unwrap_or(());
+                    return;
+                }
+            };
+            runtime.block_on(handle_signals(sender, ready_tx));
+        });
+        ready_rx.recv().unwrap_or(());
+        signalled
+    });
+}
+
+/// Whether a signal has asked the process to stop.
+pub fn requested() -> bool {
+    SIGNALLED.get().is_some_and(|signalled| *signalled.borrow())
+}
+
+/// Turns the next signal into a request for the holder to stop, until dropped.
+pub fn listen() -> ShutdownListener {
+    install();
+    LISTENERS.fetch_add(1, Ordering::SeqCst);
+    ShutdownListener(())
+}
+
+pub struct ShutdownListener(());
+
+impl ShutdownListener {
+    /// Resolves once a signal has arrived, straight away if one already has.
+    pub async fn signalled(&self) {
+        if let Some(signalled) = SIGNALLED.get() {
+            let mut signalled = signalled.subscribe();
+            if signalled.wait_for(|s| *s).await.is_ok() {
+                return;
+            }
+        }
+        std::future::pending().await
+    }
+}
+
+impl Drop for ShutdownListener {
+    fn drop(&mut self) {
+        LISTENERS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Either asks the listeners to stop or exits with the shell's status for `signal`.
+fn on_signal(signalled: &watch::Sender<bool>, signal: i32) {
+    if LISTENERS.load(Ordering::SeqCst) == 0 || *signalled.borrow() {
+        eprintln!("Stopping on signal {}", signal);
+        std::process::exit(128 + signal);
+    }
+    signalled.send_replace(true);
+}
+
+#[cfg(unix)]
+async fn handle_signals(signalled: watch::Sender<bool>, ready: std::sync::mpsc::Sender<()>) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let handlers = (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::interrupt()),
+    );
+    ready.send(()).unwrap_or(());
+    let (mut terminate, mut interrupt) = match handlers {
+        (Ok(terminate), Ok(interrupt)) => (terminate, interrupt),
+        _ => {
+            println!("Could not handle shutdown signals");
+            return;
+        }
+    };
+    loop {
+        tokio::select! {
+            _ = terminate.recv() => on_signal(&signalled, 15),
+            _ = interrupt.recv() => on_signal(&signalled, 2),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn handle_signals(signalled: watch::Sender<bool>, ready: std::sync::mpsc::Sender<()>) {
+    ready.send(()).unwrap_or(());
+    while tokio::signal::ctrl_c().await.is_ok() {
+        on_signal(&signalled, 2);
+    }
+}
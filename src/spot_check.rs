@@ -0,0 +1,158 @@
+use std::path::Path;
+
+use rug::Integer;
+
+use crate::pi_math::{closed_form_l_m_x, BinarySplit};
+use crate::reducer::{data_row, for_each_data_file, DataRow, ReducerError};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct SpotCheckReport {
+    /// The n of every sampled row, or the first n of a triple row.
+    pub checked: Vec<i128>,
+    /// The n of every sampled row that disagrees with its recomputed values,
+    /// and the columns that differ.
+    pub mismatches: Vec<(i128, Vec<String>)>,
+}
+
+/// Recomputes `samples` randomly chosen rows of an archive and compares them
+/// with the stored values: L, M and X from factorials for a term row, and P, Q
+/// and T by binary splitting for a triple row.
+///
+/// Rows are chosen with reservoir sampling, so only the sampled rows are
+/// kept in memory while the archive is read.
+pub fn spot_check_archive(
+    path: &Path,
+    samples: usize,
+    seed: Option<u64>,
+) -> Result<SpotCheckReport, ReducerError> {
+    let mut rng = match seed {
+        Some(seed) => fastrand::Rng::with_seed(seed),
+        None => fastrand::Rng::new(),
+    };
+    let mut reservoir: Vec<Vec<Integer>> = Vec::with_capacity(samples);
+    let mut rows_seen: usize = 0;
+    for_each_data_file(path, |_, format, reader| {
+        format.decode_rows(reader, &mut |row| {
+            rows_seen += 1;
+            if reservoir.len() < samples {
+                reservoir.push(row);
+            } else {
+                let slot = rng.usize(..rows_seen);
+                if slot < samples {
+                    reservoir[slot] = row;
+                }
+            }
+            Ok(())
+        })
+    })?;
+
+    let archive_name = path.display().to_string();
+    let mut report = SpotCheckReport {
+        checked: vec![],
+        mismatches: vec![],
+    };
+    for row in reservoir.into_iter() {
+        let row =
+            data_row(row).map_err(|e| ReducerError::InvalidArchive(archive_name.clone(), e))?;
+        let (n, stored, expected, columns) = match row {
+            DataRow::Term(n, l, m, x) => {
+                let (expected_l, expected_m, expected_x) = closed_form_l_m_x(&Integer::from(n));
+                (
+                    n,
+                    [l, m, x],
+                    [expected_l, expected_m, expected_x],
+                    ["l", "m", "x"],
+                )
+            }
+            DataRow::Triple(split) => {
+                let expected = BinarySplit::new(split.n_start, split.n_end).map_err(|e| {
+                    ReducerError::InvalidArchive(archive_name.clone(), e.to_string())
+                })?;
+                (
+                    split.n_start,
+                    [split.p, split.q, split.t],
+                    [expected.p, expected.q, expected.t],
+                    ["p", "q", "t"],
+                )
+            }
+        };
+
+        let bad_columns: Vec<String> = columns
+            .iter()
+            .zip(stored.iter().zip(expected.iter()))
+            .filter(|(_, (stored, expected))| stored != expected)
+            .map(|(column, _)| column.to_string())
+            .collect();
+        if !bad_columns.is_empty() {
+            report.mismatches.push((n, bad_columns));
+        }
+        report.checked.push(n);
+    }
+    report.checked.sort();
+    report.mismatches.sort();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pi_math::CalcPi;
+    use std::fs::{create_dir_all, remove_file, File};
+
+    #[test]
+    fn test_spot_check_good_archive() {
+        let mut calc_pi = CalcPi::new(0, 30, Some("./testing/spot_check")).unwrap();
+        calc_pi.set_data_handler_archive_id(0, 9007);
+        calc_pi.calc_pi_terms().unwrap();
+        let archive_path = Path::new("./pi_9007_0.tar.gz");
+
+        let report = spot_check_archive(archive_path, 10, Some(7)).unwrap();
+        remove_file(archive_path).unwrap();
+        assert_eq!(report.checked.len(), 10);
+        assert!(report.mismatches.is_empty());
+    }
+
+    #[test]
+    fn test_spot_check_triples() {
+        let mut calc_pi = CalcPi::new(3, 40, Some("./testing/spot_check")).unwrap();
+        calc_pi.set_terms_per_triple(9);
+        calc_pi.set_data_handler_archive_id(1, 9007);
+        calc_pi.calc_pi_terms().unwrap();
+        let archive_path = Path::new("./pi_9007_1.tar.gz");
+
+        let report = spot_check_archive(archive_path, 10, Some(3)).unwrap();
+        remove_file(archive_path).unwrap();
+        assert_eq!(report.checked, vec![3, 12, 21, 30, 39]);
+        assert!(report.mismatches.is_empty());
+    }
+
+    #[test]
+    fn test_spot_check_flags_bad_row() {
+        let mut rows = String::from("n,l,m,x,\n");
+        for n in 0..6 {
+            let (l, mut m, x) = closed_form_l_m_x(&Integer::from(n));
+            if n == 4 {
+                m += 1;
+            }
+            rows.push_str(&format!("{},{},{},{},\n", n, l, m, x));
+        }
+        create_dir_all("./testing/spot_check").unwrap();
+        let archive_path = Path::new("./testing/spot_check/pi_bad.tar.gz");
+        let enc = flate2::write::GzEncoder::new(
+            File::create(archive_path).unwrap(),
+            flate2::Compression::fast(),
+        );
+        let mut tar = tar::Builder::new(enc);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(rows.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, "output_0/data0.csv", rows.as_bytes())
+            .unwrap();
+        tar.into_inner().unwrap().finish().unwrap();
+
+        let report = spot_check_archive(archive_path, 6, Some(1)).unwrap();
+        assert_eq!(report.checked, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(report.mismatches, vec![(4, vec!["m".to_string()])]);
+    }
+}
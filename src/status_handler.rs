@@ -1,5 +1,9 @@
 use serde::{Deserialize, Serialize};
-use std::thread::sleep;
+use std::fmt;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
 use serde_json;
@@ -7,10 +11,18 @@ use serde_json;
 use isahc;
 use isahc::config::{RedirectPolicy, VersionNegotiation};
 use isahc::prelude::*;
+use isahc::{AsyncBody, Request, Response};
 
 use tokio::sync::mpsc;
 
+use crate::artifact_store::{self, Artifact, ArtifactStore};
+use crate::checkpoint::Checkpoint;
+use crate::config::WorkerConfig;
+use crate::job_status::JobStatus;
 use crate::pi_math::CalcPi;
+use crate::retry::{classify_status, RetryError, RetryPolicy, StatusClass};
+use crate::scheduler::LocalScheduler;
+use crate::shutdown;
 
 #[derive(Debug, Clone)]
 pub enum StatusHandlerError {
@@ -19,14 +31,60 @@ pub enum StatusHandlerError {
     ErrorUpdatingNodeInfo(String),
     ErrorUpdatingStatus(String),
     ErrorUpdatingPercentageComplete(String),
+    NoJobsLeft,
+    NoJobSelected,
+    InvalidStatusTransition(JobStatus, JobStatus),
+    JobFailed(String),
+    LeaseRevoked,
+    JobPaused,
+    ErrorUploadingArchive(String),
+    TooManyRejections(usize),
 }
 
-impl StatusHandlerError {}
+impl fmt::Display for StatusHandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StatusHandlerError::ErrorGettingJob(_s) => write!(f, "Could not get a job: {}", _s),
+            StatusHandlerError::ErrorUnpackingJob(_s) => {
+                write!(f, "Could not read the job: {}", _s)
+            }
+            StatusHandlerError::ErrorUpdatingNodeInfo(_s) => {
+                write!(f, "Could not update node info: {}", _s)
+            }
+            StatusHandlerError::ErrorUpdatingStatus(_s) => {
+                write!(f, "Could not update the job status: {}", _s)
+            }
+            StatusHandlerError::ErrorUpdatingPercentageComplete(_s) => {
+                write!(f, "Could not update the percentage complete: {}", _s)
+            }
+            StatusHandlerError::NoJobsLeft => write!(f, "There are no jobs left"),
+            StatusHandlerError::NoJobSelected => write!(f, "No job has been selected"),
+            StatusHandlerError::InvalidStatusTransition(_from, _to) => {
+                write!(f, "A job cannot go from {} to {}", _from, _to)
+            }
+            StatusHandlerError::JobFailed(_s) => write!(f, "The job failed: {}", _s),
+            StatusHandlerError::LeaseRevoked => {
+                write!(f, "The coordinator took the job back, so it was stopped")
+            }
+            StatusHandlerError::JobPaused => {
+                write!(f, "The job was checkpointed and paused on a signal")
+            }
+            StatusHandlerError::ErrorUploadingArchive(_s) => {
+                write!(f, "Could not upload the archive: {}", _s)
+            }
+            StatusHandlerError::TooManyRejections(_n) => {
+                write!(f, "Turned down {} jobs this machine cannot run", _n)
+            }
+        }
+    }
+}
 
 pub struct StatusHandler {
-    job_status: i32,
+    job_status: JobStatus,
 
     api_url: String,
+    config: WorkerConfig,
+    retry_policy: RetryPolicy,
 
     cores_available: i32,
     current_memory: f32,
@@ -37,6 +95,9 @@ pub struct StatusHandler {
     https_client: isahc::HttpClient,
 
     job_info: Option<JobInfo>,
+
+    /// Takes jobs from here instead of the API, and reports nothing over the network.
+    local_scheduler: Option<LocalScheduler>,
 }
 
 #[derive(Debug)]
@@ -51,50 +112,91 @@ impl PercentUpdate {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-struct PStatusUpdate {
-    id: f32,
-    percentage_complete: f32,
+pub struct PStatusUpdate {
+    #[serde(with = "crate::exact")]
+    pub id: u64,
+    pub percentage_complete: f32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct JobBatch {
-    cpu_needed: f32,
-    ram_needed: f32,
-    id: f32,
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JobBatch {
+    pub cpu_needed: f32,
+    pub ram_needed: f32,
+    #[serde(with = "crate::exact")]
+    pub id: u64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct JobArgs {
-    start_n: f32,
-    end_n: f32,
-    status_update_interval: f32,
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JobArgs {
+    #[serde(with = "crate::exact")]
+    pub start_n: i128,
+    #[serde(with = "crate::exact")]
+    pub end_n: i128,
+    #[serde(with = "crate::exact")]
+    pub status_update_interval: i128,
+    #[serde(default)]
+    pub output_format: Option<String>,
+    /// Write one binary splitting triple per this many terms instead of every term.
+    #[serde(default)]
+    pub terms_per_triple: Option<u64>,
+}
+
+impl JobArgs {
+    /// Why a worker cannot run these arguments, if it cannot.
+    pub fn problem(&self) -> Option<&'static str> {
+        if self.start_n < 0 {
+            Some("The job starts before term 0")
+        } else if self.start_n >= self.end_n {
+            Some("The job has no terms to compute")
+        } else if self.status_update_interval <= 0 {
+            Some("The job's status update interval is not positive")
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JobInfo {
+    #[serde(with = "crate::exact")]
+    pub id: u64,
+    pub job_batch: JobBatch,
+    pub job_args: JobArgs,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-struct JobInfo {
-    id: f32,
-    job_batch: JobBatch,
-    job_args: JobArgs,
+pub struct SetStatus {
+    #[serde(with = "crate::exact")]
+    pub id: u64,
+    pub status: JobStatus,
+    /// Why the job failed, sent with `JobStatus::Failed`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Where the archive was uploaded, sent with `JobStatus::Completed`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifact: Option<Artifact>,
 }
 
-#[derive(Serialize, Deserialize)]
-struct SetStatus {
-    id: f32,
-    status: i8,
+/// Renews the lease on a running job.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Heartbeat {
+    #[serde(with = "crate::exact")]
+    pub id: u64,
 }
 
-#[derive(Serialize, Deserialize)]
-struct NodeInfo {
-    id: f32,
-    available_cores: i32,
-    available_ram: f32,
-    process_id: i32,
-    cluster_id: i32,
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NodeInfo {
+    #[serde(with = "crate::exact")]
+    pub id: u64,
+    pub available_cores: i32,
+    pub available_ram: f32,
+    pub process_id: i32,
+    pub cluster_id: i32,
 }
 
 impl NodeInfo {
     pub fn new(
-        id: f32,
+        id: u64,
         available_cores: i32,
         available_ram: f32,
         process_id: i32,
@@ -112,12 +214,18 @@ impl NodeInfo {
 
 impl StatusHandler {
     pub fn new(api_url: String) -> StatusHandler {
+        StatusHandler::from_config(WorkerConfig {
+            api_url,
+            ..WorkerConfig::default()
+        })
+    }
+    pub fn from_config(config: WorkerConfig) -> StatusHandler {
         use sysinfo::SystemExt;
         let s = sysinfo::System::new_all();
         StatusHandler {
-            job_status: 0,
+            job_status: JobStatus::Offered,
 
-            api_url,
+            api_url: config.api_url.clone(),
 
             cores_available: num_cpus::get() as i32,
             current_memory: (s.total_memory() / 1024 / 1024) as f32,
@@ -125,7 +233,7 @@ impl StatusHandler {
             job_info: None,
 
             https_client: isahc::HttpClient::builder()
-                .timeout(Duration::from_secs(60))
+                .timeout(Duration::from_secs(config.timeout_secs))
                 .redirect_policy(RedirectPolicy::Limit(10))
                 .version_negotiation(VersionNegotiation::http11())
                 .build()
@@ -133,214 +241,451 @@ impl StatusHandler {
 
             process_id: -1,
             cluster_id: -1,
+
+            retry_policy: RetryPolicy::from_config(&config),
+            config,
+
+            local_scheduler: None,
         }
     }
+    /// Runs the jobs of `scheduler` through the same dispatch path as API jobs, with no network.
+    pub fn with_local_scheduler(config: WorkerConfig, scheduler: LocalScheduler) -> StatusHandler {
+        let mut sh = StatusHandler::from_config(config);
+        sh.local_scheduler = Some(scheduler);
+        sh
+    }
     #[tokio::main]
     pub async fn get_job(&mut self) -> Result<(), StatusHandlerError> {
-        let mut x_ids: Vec<u8> = vec![];
-        let mut job_selected = false;
-        let mut err_count = 0;
-        while !&job_selected {
-            let req = isahc::Request::put(self.api_url.clone() + "/worker-nodes/get-job")
-                .body(serde_json::to_string(&x_ids).unwrap())
-                .unwrap();
-            let resp = self
-                .https_client
-                .send_async(req)
-                .await
-                .unwrap()
-                .json()
-                .await;
-
-            println!("Request: {:?}", resp);
-
-            match resp {
-                Ok(job) => {
-                    println!("Job selected: {:?}", job);
-                    self.job_info = Some(job);
-                }
-                Err(e) => {
-                    if err_count < 5 {
-                        println!("Error: {:?}", e);
-                        err_count += 1;
-                        sleep(Duration::from_millis(5000));
-                        continue;
-                    } else {
-                        return Err(StatusHandlerError::ErrorUnpackingJob(e.to_string()));
-                    }
-                }
+        if let Some(scheduler) = self.local_scheduler.as_mut() {
+            self.job_info = scheduler.next_job();
+            if self.job_info.is_none() {
+                return Err(StatusHandlerError::NoJobsLeft);
             }
-
-            if self.job_info.as_ref().unwrap().job_batch.cpu_needed as i64
-                > self.cores_available as i64
-            {
-                println!("Not enough cores available");
-                x_ids.push(self.job_info.as_ref().unwrap().id as u8);
-                self.reject_job().await;
-                continue;
-            }
-
-            if self.job_info.as_ref().unwrap().job_batch.ram_needed as f64
-                > self.current_memory as f64
-            {
-                println!("Not enough memory available");
-                x_ids.push(self.job_info.as_ref().unwrap().id as u8);
-                self.reject_job().await;
-                continue;
+            self.job_status = JobStatus::Offered;
+            return self.accept_job().await;
+        }
+        let mut x_ids: Vec<u64> = vec![];
+        loop {
+            let job = self.request_job(&x_ids).await?;
+            println!("Job selected: {:?}", job);
+            self.job_info = Some(job);
+            self.job_status = JobStatus::Offered;
+
+            let reason = if let Some(problem) = self.job()?.job_args.problem() {
+                problem
+            } else if self.job()?.job_batch.cpu_needed as i64 > self.cores_available as i64 {
+                "Not enough cores available"
+            } else if self.job()?.job_batch.ram_needed as f64 > self.current_memory as f64 {
+                "Not enough memory available"
+            } else {
+                return self.accept_job().await;
+            };
+            println!("{}", reason);
+            x_ids.push(self.job()?.id);
+            self.reject_job().await?;
+
+            // Leave the jobs for a bigger machine rather than asking forever
+            if x_ids.len() >= self.config.max_rejections as usize {
+                println!("Turned down {} jobs, giving up", x_ids.len());
+                tokio::time::sleep(Duration::from_millis(self.config.rejection_backoff_ms)).await;
+                return Err(StatusHandlerError::TooManyRejections(x_ids.len()));
             }
-
-            self.accept_job().await;
-            job_selected = true;
         }
-        Ok(())
     }
     #[tokio::main]
-    pub async fn dispatch_job(&mut self) {
-        let job = self.job_info.as_ref().unwrap();
-        let start_n = job.job_args.start_n;
-        let end_n = job.job_args.end_n;
-        let mut calc_pi = CalcPi::new(start_n as i128, end_n as i128, Some("./"));
-        calc_pi.set_status_update_interval(
-            self.job_info
-                .as_ref()
-                .unwrap()
-                .job_args
-                .status_update_interval as i128,
-        );
-        calc_pi.set_data_handler_archive_id(
-            self.job_info.as_ref().unwrap().id as i32,
-            self.job_info.as_ref().unwrap().job_batch.id as i32,
-        );
-        self.job_status = 4;
-        self.write_new_status().await.unwrap();
+    pub async fn dispatch_job(&mut self) -> Result<(), StatusHandlerError> {
+        self.set_status(JobStatus::Running).await?;
+        let store = artifact_store::from_config(&self.config)
+            .map_err(|e| StatusHandlerError::ErrorUploadingArchive(e.to_string()))?;
+        let mut calc_pi = self.prepare_calc_pi()?;
+        // Set by a revoked lease or a shutdown signal
+        let stop = Arc::new(AtomicBool::new(false));
+        calc_pi.set_cancel_flag(Arc::clone(&stop));
         let (tx, mut rx) = mpsc::channel(32);
-
-        tokio::spawn(async move {
-            calc_pi.calc_pi_terms_with_status(tx).await;
+        // Held until the job is reported, so a signal during the upload
+        // is left for the caller rather than ending the process
+        let listener = shutdown::listen();
+        let shutdown = listener.signalled();
+        tokio::pin!(shutdown);
+        let mut paused = false;
+        let mut status_error = None;
+
+        // The calculation only yields between status updates, so it gets its own
+        // thread rather than holding a runtime worker the heartbeat needs
+        let runtime = tokio::runtime::Handle::current();
+        let calc = tokio::task::spawn_blocking(move || {
+            runtime
+                .block_on(calc_pi.calc_pi_terms_with_status(tx))
+                .map(|()| calc_pi.archive_path())
         });
+        let heartbeat = self.spawn_heartbeat(&stop)?;
 
-        while let Some(message) = rx.recv().await {
-            self.update_percent_complete(message).await.unwrap();
+        loop {
+            tokio::select! {
+                message = rx.recv() => match message {
+                    Some(message) if status_error.is_none() => {
+                        if let Err(e) = self.update_percent_complete(message).await {
+                            println!("Could not update the status, stopping the job at the next term");
+                            stop.store(true, Ordering::Relaxed);
+                            status_error = Some(e);
+                        }
+                    }
+                    // Still drained so the calculation is never left waiting to send
+                    Some(_) => {}
+                    None => break,
+                },
+                () = &mut shutdown, if !paused => {
+                    println!("Shutting down, stopping the job at the next term");
+                    paused = true;
+                    stop.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+        let result = calc.await;
+        if let Some(heartbeat) = heartbeat {
+            heartbeat.abort();
+        }
+        if let Some(e) = status_error {
+            return Err(e);
+        }
+        match result {
+            // A signal or revocation that lands once the last term is written
+            // is too late to stop anything, so the finished job still counts
+            Ok(Ok(archive_path)) => {
+                let artifact = match store {
+                    Some(store) => Some(self.upload_archive(store.into(), archive_path).await?),
+                    None => None,
+                };
+                self.complete_job(artifact).await
+            }
+            Ok(Err(e)) if e.kind() == ErrorKind::Interrupted && paused => {
+                self.set_status(JobStatus::Paused).await?;
+                Err(StatusHandlerError::JobPaused)
+            }
+            Ok(Err(e)) if e.kind() == ErrorKind::Interrupted && stop.load(Ordering::Relaxed) => {
+                // The coordinator has requeued the job, and whichever worker it
+                // goes to next starts it afresh, so nothing here is worth resuming
+                discard_checkpoint(&self.checkpoint_path()?);
+                self.job_status = JobStatus::Failed;
+                Err(StatusHandlerError::LeaseRevoked)
+            }
+            Ok(Err(e)) => Err(StatusHandlerError::JobFailed(e.to_string())),
+            Err(e) => Err(StatusHandlerError::JobFailed(e.to_string())),
+        }
+    }
+    /// Tells the server the current job failed because of `error`, if it is running.
+    #[tokio::main]
+    pub async fn report_failure(
+        &mut self,
+        error: &StatusHandlerError,
+    ) -> Result<(), StatusHandlerError> {
+        if self.job_info.is_none() || !self.job_status.can_transition_to(JobStatus::Failed) {
+            return Ok(());
         }
-        self.complete_job().await;
+        self.job_status = JobStatus::Failed;
+        self.write_new_status(Some(error.to_string()), None).await
     }
     pub fn set_node_info(&mut self, id: i32, cluster_id: i32) {
         self.process_id = id;
         self.cluster_id = cluster_id;
     }
-    async fn reject_job(&mut self) {
-        self.job_status = 1;
-        self.write_new_status().await.unwrap();
+    fn job(&self) -> Result<&JobInfo, StatusHandlerError> {
+        self.job_info
+            .as_ref()
+            .ok_or(StatusHandlerError::NoJobSelected)
+    }
+    async fn request_job(&self, x_ids: &[u64]) -> Result<JobInfo, StatusHandlerError> {
+        let body = serde_json::to_string(x_ids).unwrap_or_default();
+        let mut resp = self
+            .send("Getting a job", || {
+                Request::put(self.api_url.clone() + "/worker-nodes/get-job").body(body.clone())
+            })
+            .await
+            .map_err(StatusHandlerError::ErrorGettingJob)?;
+        resp.json()
+            .await
+            .map_err(|e| StatusHandlerError::ErrorUnpackingJob(e.to_string()))
+    }
+    /// Sends the request built by `request`, retrying under the retry policy
+    /// while the failure is a transport error or a retryable status.
+    async fn send<F>(&self, what: &str, request: F) -> Result<Response<AsyncBody>, String>
+    where
+        F: Fn() -> Result<Request<String>, isahc::http::Error>,
+    {
+        let request = &request;
+        self.retry_policy
+            .retry(what, || async move {
+                let req = request().map_err(|e| RetryError::Fatal(e.to_string()))?;
+                let resp = self
+                    .https_client
+                    .send_async(req)
+                    .await
+                    .map_err(|e| RetryError::Retryable(e.to_string()))?;
+                match classify_status(resp.status().as_u16()) {
+                    StatusClass::Success => Ok(resp),
+                    StatusClass::Retryable => {
+                        Err(RetryError::Retryable(format!("HTTP {}", resp.status())))
+                    }
+                    StatusClass::Fatal => Err(RetryError::Fatal(format!("HTTP {}", resp.status()))),
+                }
+            })
+            .await
+    }
+    /// Starts renewing the current job's lease every heartbeat interval,
+    /// setting `stop` if the coordinator takes the job back.
+    fn spawn_heartbeat(
+        &self,
+        stop: &Arc<AtomicBool>,
+    ) -> Result<Option<tokio::task::JoinHandle<()>>, StatusHandlerError> {
+        if self.local_scheduler.is_some() || self.config.heartbeat_interval_ms == 0 {
+            return Ok(None);
+        }
+        let body = serde_json::to_string(&Heartbeat { id: self.job()?.id }).unwrap_or_default();
+        Ok(Some(tokio::spawn(renew_lease(
+            self.https_client.clone(),
+            self.api_url.clone() + "/worker-nodes/heartbeat",
+            body,
+            Duration::from_millis(self.config.heartbeat_interval_ms),
+            Arc::clone(stop),
+        ))))
+    }
+    fn checkpoint_path(&self) -> Result<String, StatusHandlerError> {
+        let job = self.job()?;
+        Ok(Path::new(&self.config.output_dir)
+            .join(format!("checkpoint_{}_{}.json", job.job_batch.id, job.id))
+            .to_string_lossy()
+            .to_string())
+    }
+    /// Builds the `CalcPi` for the current job, resuming it from its checkpoint if there is one.
+    fn prepare_calc_pi(&self) -> Result<CalcPi, StatusHandlerError> {
+        let job = self.job()?;
+        if let Some(problem) = job.job_args.problem() {
+            return Err(StatusHandlerError::JobFailed(problem.to_string()));
+        }
+        let status_update_interval = self
+            .config
+            .status_update_interval
+            .unwrap_or(job.job_args.status_update_interval);
+        let checkpoint_path = self.checkpoint_path()?;
+        let file_type = job.job_args.output_format.as_deref().unwrap_or("csv");
+        let terms_per_triple = job.job_args.terms_per_triple.map(i128::from);
+        let resumed = match CalcPi::resume_from_checkpoint(&checkpoint_path) {
+            Ok(calc_pi)
+                if calc_pi.is_job(
+                    job.job_args.start_n,
+                    job.job_args.end_n,
+                    file_type,
+                    terms_per_triple,
+                ) =>
+            {
+                Some(calc_pi)
+            }
+            Ok(calc_pi) => {
+                println!(
+                    "Discarding the checkpoint at {}, it is for a different job",
+                    checkpoint_path
+                );
+                calc_pi.remove_output();
+                Checkpoint::remove_with_parts(&checkpoint_path);
+                None
+            }
+            Err(_) => None,
+        };
+        let mut calc_pi = match resumed {
+            Some(calc_pi) => calc_pi,
+            None => {
+                let mut calc_pi = CalcPi::with_output_format(
+                    job.job_args.start_n,
+                    job.job_args.end_n,
+                    Some(&self.config.output_dir),
+                    file_type,
+                )
+                .map_err(|e| StatusHandlerError::JobFailed(e.to_string()))?;
+                if let Some(terms) = terms_per_triple {
+                    calc_pi.set_terms_per_triple(terms);
+                }
+                calc_pi
+            }
+        };
+        calc_pi.set_status_update_interval(status_update_interval);
+        calc_pi.apply_config(&self.config);
+        calc_pi.set_checkpoint(&checkpoint_path, status_update_interval);
+        calc_pi
+            .set_threads((job.job_batch.cpu_needed as i32).clamp(1, self.cores_available) as usize);
+        calc_pi.set_data_handler_archive_id(job.id, job.job_batch.id);
+        Ok(calc_pi)
+    }
+    async fn reject_job(&mut self) -> Result<(), StatusHandlerError> {
+        self.set_status(JobStatus::Rejected).await?;
         self.job_info = None;
+        Ok(())
+    }
+    async fn accept_job(&mut self) -> Result<(), StatusHandlerError> {
+        self.update_node_info().await?;
+        self.set_status(JobStatus::Accepted).await
     }
-    async fn accept_job(&mut self) {
-        self.job_status = 3;
-        self.update_node_info().await.unwrap();
-        self.write_new_status().await.unwrap();
+    async fn complete_job(&mut self, artifact: Option<Artifact>) -> Result<(), StatusHandlerError> {
+        self.transition(JobStatus::Completed)?;
+        self.write_new_status(None, artifact).await
+    }
+    /// Moves the job to `next` and reports it, if the move is a valid transition.
+    async fn set_status(&mut self, next: JobStatus) -> Result<(), StatusHandlerError> {
+        self.transition(next)?;
+        self.write_new_status(None, None).await
+    }
+    fn transition(&mut self, next: JobStatus) -> Result<(), StatusHandlerError> {
+        if !self.job_status.can_transition_to(next) {
+            return Err(StatusHandlerError::InvalidStatusTransition(
+                self.job_status,
+                next,
+            ));
+        }
+        self.job_status = next;
+        Ok(())
     }
-    async fn complete_job(&mut self) {
-        self.job_status = 5;
-        self.write_new_status().await.unwrap();
+    /// Uploads the finished archive, retrying under the retry policy.
+    async fn upload_archive(
+        &self,
+        store: Arc<dyn ArtifactStore>,
+        archive_path: String,
+    ) -> Result<Artifact, StatusHandlerError> {
+        let artifact = self
+            .retry_policy
+            .retry("Uploading the archive", || {
+                let store = Arc::clone(&store);
+                let archive_path = archive_path.clone();
+                async move {
+                    let uploaded = tokio::task::spawn_blocking(move || {
+                        artifact_store::upload(store.as_ref(), Path::new(&archive_path))
+                    })
+                    .await
+                    .map_err(|e| RetryError::Fatal(e.to_string()))?;
+                    uploaded.map_err(|e| {
+                        if e.is_retryable() {
+                            RetryError::Retryable(e.to_string())
+                        } else {
+                            RetryError::Fatal(e.to_string())
+                        }
+                    })
+                }
+            })
+            .await
+            .map_err(StatusHandlerError::ErrorUploadingArchive)?;
+        println!("Uploaded the archive to {}", artifact.location);
+        Ok(artifact)
     }
     async fn update_node_info(&mut self) -> Result<(), StatusHandlerError> {
-        let mut err_count = 0;
-        let node_info = NodeInfo::new(
-            self.job_info.as_ref().unwrap().id,
+        if self.local_scheduler.is_some() {
+            return Ok(());
+        }
+        let body = serde_json::to_string(&NodeInfo::new(
+            self.job()?.id,
             self.cores_available,
             self.current_memory,
             self.process_id,
             self.cluster_id,
-        );
-        loop {
-            let req = isahc::Request::patch(self.api_url.clone() + "/worker-nodes/set-info")
-                .body(serde_json::to_string(&node_info).unwrap())
-                .unwrap();
-
-            let resp = self.https_client.send_async(req).await;
-
-            match resp {
-                Ok(_) => {
-                    break;
-                }
-                Err(e) => {
-                    if err_count < 5 {
-                        println!("Update Node Info Error: {:?}", e);
-                        err_count += 1;
-                        continue;
-                    } else {
-                        return Err(StatusHandlerError::ErrorUpdatingNodeInfo(e.to_string()));
-                    }
-                }
-            }
-        }
+        ))
+        .unwrap_or_default();
+        self.send("Updating node info", || {
+            Request::patch(self.api_url.clone() + "/worker-nodes/set-info").body(body.clone())
+        })
+        .await
+        .map_err(StatusHandlerError::ErrorUpdatingNodeInfo)?;
         Ok(())
     }
-    async fn write_new_status(&mut self) -> Result<(), StatusHandlerError> {
+    async fn write_new_status(
+        &mut self,
+        message: Option<String>,
+        artifact: Option<Artifact>,
+    ) -> Result<(), StatusHandlerError> {
         let s = SetStatus {
-            id: self.job_info.as_ref().unwrap().id,
-            status: self.job_status as i8,
+            id: self.job()?.id,
+            status: self.job_status,
+            message,
+            artifact,
         };
-        let mut err_count = 0;
-        loop {
-            let req = isahc::Request::patch(self.api_url.clone() + "/worker-nodes/set-status")
-                .body(serde_json::to_string(&s).unwrap());
-            let resp = self.https_client.send_async(req.unwrap()).await;
-            match resp {
-                Ok(_) => {
-                    break;
-                }
-                Err(e) => {
-                    if err_count < 5 {
-                        println!("Error Writing Status: {:?}", e);
-                        err_count += 1;
-                        sleep(Duration::from_millis(5000));
-                        continue;
-                    } else {
-                        return Err(StatusHandlerError::ErrorUpdatingStatus(e.to_string()));
-                    }
-                }
+        if self.local_scheduler.is_some() {
+            match &s.message {
+                Some(message) => println!("Job {} status {}: {}", s.id, s.status, message),
+                None => println!("Job {} status {}", s.id, s.status),
             }
+            return Ok(());
         }
+        let body = serde_json::to_string(&s).unwrap_or_default();
+        self.send("Writing the job status", || {
+            Request::patch(self.api_url.clone() + "/worker-nodes/set-status").body(body.clone())
+        })
+        .await
+        .map_err(StatusHandlerError::ErrorUpdatingStatus)?;
         Ok(())
     }
     async fn update_percent_complete(
         &mut self,
         percent: PercentUpdate,
     ) -> Result<(), StatusHandlerError> {
-        let mut err_count = 0;
-        loop {
-            let req =
-                isahc::Request::patch(self.api_url.clone() + "/worker-nodes/update-percentage")
-                    .body(
-                        serde_json::to_string(&PStatusUpdate {
-                            id: self.job_info.as_ref().unwrap().id,
-                            percentage_complete: percent.percent,
-                        })
-                        .unwrap(),
-                    );
-            let resp = self.https_client.send_async(req.unwrap()).await;
-            match resp {
-                Ok(_) => {
-                    break;
-                }
-                Err(e) => {
-                    if err_count < 5 {
-                        println!("Update Percent Error: {:?}", e);
-                        err_count += 1;
-                        sleep(Duration::from_millis(5000));
-                        continue;
-                    } else {
-                        return Err(StatusHandlerError::ErrorUpdatingPercentageComplete(
-                            e.to_string(),
-                        ));
-                    }
-                }
-            }
+        if self.local_scheduler.is_some() {
+            return Ok(());
         }
+        let body = serde_json::to_string(&PStatusUpdate {
+            id: self.job()?.id,
+            percentage_complete: percent.percent,
+        })
+        .unwrap_or_default();
+        self.send("Updating the percentage complete", || {
+            Request::patch(self.api_url.clone() + "/worker-nodes/update-percentage")
+                .body(body.clone())
+        })
+        .await
+        .map_err(StatusHandlerError::ErrorUpdatingPercentageComplete)?;
         Ok(())
     }
 }
 
+/// Removes the checkpoint at `checkpoint_path` along with those of its parts
+/// and the output it was resuming.
+fn discard_checkpoint(checkpoint_path: &str) {
+    if let Ok(calc_pi) = CalcPi::resume_from_checkpoint(checkpoint_path) {
+        calc_pi.remove_output();
+    }
+    Checkpoint::remove_with_parts(checkpoint_path);
+}
+
+/// Sends `body` to `url` every `interval` until aborted, or until the
+/// coordinator answers 410 Gone, which sets `stop`.
+///
+/// Other failures are only logged: the next heartbeat is the retry, and the
+/// lease outlives a few missed ones.
+async fn renew_lease(
+    client: isahc::HttpClient,
+    url: String,
+    body: String,
+    interval: Duration,
+    stop: Arc<AtomicBool>,
+) {
+    loop {
+        tokio::time::sleep(interval).await;
+        let req = match Request::patch(&url).body(body.clone()) {
+            Ok(req) => req,
+            Err(e) => {
+                println!("Heartbeat Error: {:?}", e);
+                return;
+            }
+        };
+        match client.send_async(req).await {
+            Ok(resp) if resp.status().as_u16() == 410 => {
+                println!("Lease revoked, stopping the job");
+                stop.store(true, Ordering::Relaxed);
+                return;
+            }
+            Ok(resp) if !resp.status().is_success() => {
+                println!("Heartbeat Error: HTTP {}", resp.status())
+            }
+            Ok(_) => {}
+            Err(e) => println!("Heartbeat Error: {:?}", e),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::status_handler::StatusHandler;
@@ -367,7 +712,7 @@ mod tests {
     fn test_spawn() {
         let mut s = StatusHandler::new("https://piapi.oscorp.ml".to_string());
         s.get_job().unwrap();
-        s.dispatch_job();
+        s.dispatch_job().unwrap();
     }
 
     #[test]
@@ -375,6 +720,4 @@ mod tests {
         let mut s = StatusHandler::new("https://piapi.oscorp.ml".to_string());
         s.get_job().unwrap();
     }
-
-
 }
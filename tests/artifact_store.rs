@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::fs::create_dir_all;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use axum::body::Bytes;
+use axum::extract::{Path as UrlPath, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::put;
+use axum::Router;
+use sha2::{Digest, Sha256};
+
+use calculating_pi_rust::artifact_store::{
+    sha256_file, sign_v4, upload, ArtifactStore, ArtifactStoreError, HttpPutStore, S3Credentials,
+    S3Store,
+};
+use calculating_pi_rust::config::WorkerConfig;
+use calculating_pi_rust::coordinator::Coordinator;
+use calculating_pi_rust::job_status::JobStatus;
+use calculating_pi_rust::scheduler::LocalScheduler;
+use calculating_pi_rust::status_handler::StatusHandler;
+
+mod common;
+use common::{start, start_router, worker_config};
+
+const ACCESS_KEY: &str = "minio";
+const SECRET_KEY: &str = "minio-secret";
+
+type Objects = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+fn credentials(secret_key: &str) -> S3Credentials {
+    S3Credentials {
+        region: "us-east-1".to_string(),
+        access_key: ACCESS_KEY.to_string(),
+        secret_key: secret_key.to_string(),
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> &'a str {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+}
+
+/// Stores a plain PUT if its `x-checksum-sha256` matches the body.
+async fn http_put(
+    State(objects): State<Objects>,
+    UrlPath(name): UrlPath<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    if header(&headers, "x-checksum-sha256") != format!("{:x}", Sha256::digest(&body)) {
+        return StatusCode::BAD_REQUEST;
+    }
+    objects.lock().unwrap().insert(name, body.to_vec());
+    StatusCode::CREATED
+}
+
+/// Stores an S3 PUT Object if it is signed with `SECRET_KEY` and its
+/// `x-amz-content-sha256` matches the body, like MinIO would.
+async fn s3_put(
+    State(objects): State<Objects>,
+    UrlPath((bucket, key)): UrlPath<(String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    let payload_sha256 = header(&headers, "x-amz-content-sha256");
+    let amz_date = header(&headers, "x-amz-date");
+    let expected = sign_v4(
+        &credentials(SECRET_KEY),
+        "PUT",
+        &format!("/{}/{}", bucket, key),
+        &[
+            ("host", header(&headers, "host")),
+            ("x-amz-content-sha256", payload_sha256),
+            ("x-amz-date", amz_date),
+        ],
+        payload_sha256,
+        amz_date,
+    );
+    if header(&headers, "authorization") != expected {
+        return StatusCode::FORBIDDEN;
+    }
+    if payload_sha256 != format!("{:x}", Sha256::digest(&body)) {
+        return StatusCode::BAD_REQUEST;
+    }
+    objects
+        .lock()
+        .unwrap()
+        .insert(format!("{}/{}", bucket, key), body.to_vec());
+    StatusCode::OK
+}
+
+/// Serves an HTTP PUT endpoint under `/files` and an S3 API everywhere else,
+/// returning its URL and what has been stored.
+fn start_stand_in() -> (String, Objects) {
+    let objects = Objects::default();
+    let router = Router::new()
+        .route("/files/{name}", put(http_put))
+        .route("/{bucket}/{key}", put(s3_put))
+        .with_state(Arc::clone(&objects));
+    (start_router(router), objects)
+}
+
+fn archive(name: &str) -> std::path::PathBuf {
+    let dir = Path::new("./testing/artifact_store");
+    create_dir_all(dir).unwrap();
+    let path = dir.join(name);
+    std::fs::write(&path, format!("not really a tar of {}", name)).unwrap();
+    path
+}
+
+#[test]
+fn test_http_put_store() {
+    let (url, objects) = start_stand_in();
+    let path = archive("pi_9018_0.tar.gz");
+    let store = HttpPutStore::new(format!("{}/files/", url), std::time::Duration::from_secs(5));
+    let artifact = upload(&store.unwrap(), &path).unwrap();
+    assert_eq!(artifact.location, format!("{}/files/pi_9018_0.tar.gz", url));
+    assert_eq!(artifact.sha256, sha256_file(&path).unwrap());
+    assert_eq!(
+        objects.lock().unwrap()["pi_9018_0.tar.gz"],
+        std::fs::read(&path).unwrap()
+    );
+
+    let store = HttpPutStore::new(format!("{}/files", url), std::time::Duration::from_secs(5));
+    assert!(matches!(
+        store.unwrap().put(&path, "pi_9018_1.tar.gz", "0000"),
+        Err(ArtifactStoreError::Rejected(400, _))
+    ));
+}
+
+#[test]
+fn test_s3_store() {
+    let (url, objects) = start_stand_in();
+    let path = archive("pi_9019_0.tar.gz");
+    let timeout = std::time::Duration::from_secs(5);
+    let store = S3Store::new(credentials(SECRET_KEY), url.clone(), "pi".into(), timeout);
+    let artifact = upload(&store.unwrap(), &path).unwrap();
+    assert_eq!(artifact.location, format!("{}/pi/pi_9019_0.tar.gz", url));
+    assert_eq!(
+        objects.lock().unwrap()["pi/pi_9019_0.tar.gz"],
+        std::fs::read(&path).unwrap()
+    );
+
+    let store = S3Store::new(credentials("wrong"), url, "pi".into(), timeout).unwrap();
+    let error = upload(&store, &path).unwrap_err();
+    assert!(matches!(error, ArtifactStoreError::Rejected(403, _)));
+    assert!(!error.is_retryable());
+}
+
+#[test]
+fn test_upload_location_is_reported() {
+    let (store_url, objects) = start_stand_in();
+    let coordinator = Coordinator::in_memory();
+    let mut scheduler = LocalScheduler::new(9020, 5, 5, 1);
+    coordinator.enqueue(scheduler.next_job().unwrap()).unwrap();
+    let api_url = start(&coordinator);
+
+    let mut sh = StatusHandler::from_config(WorkerConfig {
+        artifact_store: "s3".to_string(),
+        artifact_location: store_url.clone(),
+        artifact_bucket: "pi".to_string(),
+        artifact_access_key: ACCESS_KEY.to_string(),
+        artifact_secret_key: SECRET_KEY.to_string(),
+        ..worker_config(api_url, "./testing/artifact_store")
+    });
+    sh.get_job().unwrap();
+    sh.dispatch_job().unwrap();
+
+    let archive_path = Path::new("pi_9020_0.tar.gz");
+    let sha256 = sha256_file(archive_path).unwrap();
+    assert_eq!(
+        objects.lock().unwrap()["pi/pi_9020_0.tar.gz"],
+        std::fs::read(archive_path).unwrap()
+    );
+    std::fs::remove_file(archive_path).unwrap();
+
+    let record = &coordinator.jobs()[0];
+    assert_eq!(record.status, Some(JobStatus::Completed));
+    let artifact = record.history.last().unwrap().artifact.clone().unwrap();
+    assert_eq!(
+        artifact.location,
+        format!("{}/pi/pi_9020_0.tar.gz", store_url)
+    );
+    assert_eq!(artifact.sha256, sha256);
+}
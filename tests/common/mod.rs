@@ -0,0 +1,39 @@
+use std::fs::create_dir_all;
+
+use axum::Router;
+use tokio::net::TcpListener;
+
+use calculating_pi_rust::config::WorkerConfig;
+use calculating_pi_rust::coordinator::Coordinator;
+
+/// Serves `coordinator` from its own runtime thread and returns its URL.
+pub fn start(coordinator: &Coordinator) -> String {
+    start_router(coordinator.router())
+}
+
+/// Serves `router` from its own runtime thread and returns its URL.
+pub fn start_router(router: Router) -> String {
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                tx.send(listener.local_addr().unwrap()).unwrap();
+                axum::serve(listener, router).await.unwrap();
+            })
+    });
+    format!("http://{}", rx.recv().unwrap())
+}
+
+/// A worker that talks to `api_url`, writes to `output_dir` and never retries.
+pub fn worker_config(api_url: String, output_dir: &str) -> WorkerConfig {
+    create_dir_all(output_dir).unwrap();
+    WorkerConfig {
+        api_url,
+        retry_count: 0,
+        retry_delay_ms: 0,
+        output_dir: output_dir.to_string(),
+        ..WorkerConfig::default()
+    }
+}
@@ -0,0 +1,388 @@
+use std::fs::{create_dir_all, read_dir, remove_dir_all, remove_file};
+use std::path::Path;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use axum::routing::patch;
+use axum::Router;
+use isahc::ReadResponseExt;
+
+use calculating_pi_rust::config::WorkerConfig;
+use calculating_pi_rust::coordinator::Coordinator;
+use calculating_pi_rust::job_status::JobStatus;
+use calculating_pi_rust::pi_digits::{pi_digits, reference_pi_digits};
+use calculating_pi_rust::reducer::reduce_archives;
+use calculating_pi_rust::scheduler::LocalScheduler;
+use calculating_pi_rust::status_handler::{StatusHandler, StatusHandlerError};
+
+mod common;
+use common::{start, start_router, worker_config};
+
+const OUTPUT_DIR: &str = "./testing/coordinator";
+
+#[test]
+fn test_worker_runs_batch_from_coordinator() {
+    let coordinator = Coordinator::in_memory();
+    let mut scheduler = LocalScheduler::for_digits(9012, 60, 10, 3, 1).unwrap();
+    let archive_names = scheduler.archive_names();
+    while let Some(job) = scheduler.next_job() {
+        coordinator.enqueue(job).unwrap();
+    }
+    let api_url = start(&coordinator);
+
+    for _ in 0..archive_names.len() {
+        let mut sh = StatusHandler::from_config(worker_config(api_url.clone(), OUTPUT_DIR));
+        sh.set_node_info(7, 11);
+        sh.get_job().unwrap();
+        sh.dispatch_job().unwrap();
+    }
+    let mut sh = StatusHandler::from_config(worker_config(api_url, OUTPUT_DIR));
+    assert!(sh.get_job().is_err());
+
+    for record in coordinator.jobs() {
+        let statuses: Vec<JobStatus> = record.history.iter().map(|c| c.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                JobStatus::Offered,
+                JobStatus::Accepted,
+                JobStatus::Running,
+                JobStatus::Completed
+            ]
+        );
+        assert_eq!(record.percentage_complete, 100.0);
+        let node = record.node.unwrap();
+        assert_eq!((node.process_id, node.cluster_id), (7, 11));
+    }
+
+    let sum = reduce_archives(&archive_names).unwrap();
+    for name in archive_names {
+        remove_file(name).unwrap();
+    }
+    assert_eq!(
+        pi_digits(&sum, 60, 10).unwrap(),
+        reference_pi_digits(60, 10).unwrap()
+    );
+}
+
+#[test]
+fn test_rejected_job_is_persisted() {
+    let state_path = Path::new("./testing/coordinator_state.json");
+    create_dir_all("./testing").unwrap();
+    remove_file(state_path).unwrap_or(());
+
+    let coordinator = Coordinator::open(state_path).unwrap();
+    let mut scheduler = LocalScheduler::new(9013, 10, 10, 100_000);
+    coordinator.enqueue(scheduler.next_job().unwrap()).unwrap();
+    let api_url = start(&coordinator);
+
+    // The job needs more cores than any test machine has
+    let mut sh = StatusHandler::from_config(worker_config(api_url, OUTPUT_DIR));
+    assert!(sh.get_job().is_err());
+
+    let reopened = Coordinator::open(state_path).unwrap();
+    let records = reopened.jobs();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].status, Some(JobStatus::Rejected));
+    let statuses: Vec<JobStatus> = records[0].history.iter().map(|c| c.status).collect();
+    assert_eq!(statuses, vec![JobStatus::Offered, JobStatus::Rejected]);
+}
+
+#[test]
+fn test_invalid_job_args_are_rejected() {
+    let coordinator = Coordinator::in_memory();
+    let mut scheduler = LocalScheduler::new(9028, 30, 10, 1);
+    let mut jobs: Vec<_> = std::iter::from_fn(|| scheduler.next_job()).collect();
+    jobs[0].job_args.start_n = -10;
+    jobs[1].job_args.end_n = jobs[1].job_args.start_n;
+    jobs[2].job_args.status_update_interval = 0;
+    for job in jobs {
+        coordinator.enqueue(job).unwrap();
+    }
+    let api_url = start(&coordinator);
+
+    let mut sh = StatusHandler::from_config(worker_config(api_url, OUTPUT_DIR));
+    assert!(sh.get_job().is_err());
+    for record in coordinator.jobs() {
+        assert_eq!(record.status, Some(JobStatus::Rejected));
+    }
+}
+
+#[test]
+fn test_invalid_transition_is_refused() {
+    let coordinator = Coordinator::in_memory();
+    let mut scheduler = LocalScheduler::new(9014, 10, 10, 1);
+    coordinator.enqueue(scheduler.next_job().unwrap()).unwrap();
+    let api_url = start(&coordinator);
+
+    let patch = |status: i8| {
+        let req = isahc::Request::patch(format!("{}/worker-nodes/set-status", api_url))
+            .body(format!("{{\"id\": 0, \"status\": {}}}", status))
+            .unwrap();
+        isahc::send(req).unwrap().status().as_u16()
+    };
+    // Not offered yet
+    assert_eq!(patch(5), 409);
+    let mut resp = isahc::put(format!("{}/worker-nodes/get-job", api_url), "[]").unwrap();
+    assert_eq!(resp.status().as_u16(), 200);
+    resp.text().unwrap();
+    assert_eq!(patch(5), 409);
+    assert_eq!(patch(3), 200);
+    assert_eq!(patch(2), 422);
+    assert_eq!(coordinator.jobs()[0].status, Some(JobStatus::Accepted));
+}
+
+#[test]
+fn test_failure_is_reported() {
+    let coordinator = Coordinator::in_memory();
+    let mut scheduler = LocalScheduler::new(9015, 10, 10, 1);
+    let mut job = scheduler.next_job().unwrap();
+    job.job_args.output_format = Some("parquet".to_string());
+    coordinator.enqueue(job).unwrap();
+    let api_url = start(&coordinator);
+
+    let mut sh = StatusHandler::from_config(worker_config(api_url, OUTPUT_DIR));
+    sh.get_job().unwrap();
+    let error = sh.dispatch_job().unwrap_err();
+    sh.report_failure(&error).unwrap();
+
+    let record = &coordinator.jobs()[0];
+    assert_eq!(record.status, Some(JobStatus::Failed));
+    let message = record.history.last().unwrap().message.as_ref().unwrap();
+    assert!(message.contains("parquet"), "{}", message);
+}
+
+#[test]
+fn test_revoked_lease_stops_worker() {
+    let coordinator = Coordinator::in_memory();
+    let mut scheduler = LocalScheduler::new(9016, 1_000_000, 1_000_000, 1);
+    coordinator.enqueue(scheduler.next_job().unwrap()).unwrap();
+    let api_url = start(&coordinator);
+
+    let revoker = coordinator.clone();
+    std::thread::spawn(move || {
+        while !revoker.revoke_lease(0).unwrap() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    });
+    let mut sh = StatusHandler::from_config(WorkerConfig {
+        heartbeat_interval_ms: 20,
+        ..worker_config(api_url.clone(), OUTPUT_DIR)
+    });
+    sh.get_job().unwrap();
+    let error = sh.dispatch_job().unwrap_err();
+    assert!(
+        matches!(error, StatusHandlerError::LeaseRevoked),
+        "{:?}",
+        error
+    );
+    // Nothing to report, the coordinator already took the job back
+    sh.report_failure(&error).unwrap();
+
+    let record = &coordinator.jobs()[0];
+    assert_eq!(record.status, Some(JobStatus::Requeued));
+    assert_eq!(record.history.len(), 4);
+    assert_eq!(
+        record.history.last().unwrap().message.as_deref(),
+        Some("lease revoked")
+    );
+    // The job will be handed out again, so the worker keeps nothing to resume
+    assert!(!Path::new(OUTPUT_DIR)
+        .join("checkpoint_9016_0.json")
+        .exists());
+
+    let mut sh = StatusHandler::from_config(worker_config(api_url, OUTPUT_DIR));
+    sh.get_job().unwrap();
+    assert_eq!(coordinator.jobs()[0].status, Some(JobStatus::Accepted));
+}
+
+#[test]
+fn test_failed_status_update_stops_job() {
+    let coordinator = Coordinator::in_memory();
+    let mut scheduler = LocalScheduler::new(9030, 1_000_000, 1_000_000, 1);
+    coordinator.enqueue(scheduler.next_job().unwrap()).unwrap();
+    let router = Router::new()
+        .route(
+            "/worker-nodes/update-percentage",
+            patch(|| async { StatusCode::INTERNAL_SERVER_ERROR }),
+        )
+        .fallback_service(coordinator.router());
+    let api_url = start_router(router);
+
+    let output_dir = "./testing/coordinator_status";
+    remove_dir_all(output_dir).unwrap_or(());
+    let mut sh = StatusHandler::from_config(worker_config(api_url, output_dir));
+    sh.get_job().unwrap();
+    let error = sh.dispatch_job().unwrap_err();
+    assert!(
+        matches!(
+            error,
+            StatusHandlerError::ErrorUpdatingPercentageComplete(_)
+        ),
+        "{:?}",
+        error
+    );
+
+    // The calculation had stopped before the error came back
+    let written = || {
+        read_dir(Path::new(output_dir).join("output_0"))
+            .unwrap()
+            .map(|entry| entry.unwrap().metadata().unwrap().len())
+            .sum::<u64>()
+    };
+    let stopped_at = written();
+    std::thread::sleep(Duration::from_millis(300));
+    assert_eq!(written(), stopped_at);
+}
+
+#[test]
+fn test_lease_expires_without_heartbeat() {
+    let coordinator = Coordinator::in_memory().with_lease_duration(Duration::ZERO);
+    let mut scheduler = LocalScheduler::new(9017, 10, 10, 1);
+    coordinator.enqueue(scheduler.next_job().unwrap()).unwrap();
+    let api_url = start(&coordinator);
+
+    let patch = |path: &str, body: &str| {
+        let req = isahc::Request::patch(format!("{}/worker-nodes/{}", api_url, path))
+            .body(body.to_string())
+            .unwrap();
+        isahc::send(req).unwrap().status().as_u16()
+    };
+    let mut resp = isahc::put(format!("{}/worker-nodes/get-job", api_url), "[]").unwrap();
+    resp.text().unwrap();
+    assert_eq!(patch("set-status", "{\"id\": 0, \"status\": 3}"), 200);
+    assert_eq!(patch("set-status", "{\"id\": 0, \"status\": 4}"), 200);
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(patch("heartbeat", "{\"id\": 0}"), 410);
+
+    let record = &coordinator.jobs()[0];
+    assert_eq!(record.status, Some(JobStatus::Requeued));
+    assert_eq!(
+        record.history.last().unwrap().message.as_deref(),
+        Some("lease expired")
+    );
+
+    // Another worker gets the same job
+    let mut resp = isahc::put(format!("{}/worker-nodes/get-job", api_url), "[]").unwrap();
+    let body: serde_json::Value = serde_json::from_str(&resp.text().unwrap()).unwrap();
+    assert_eq!(body["id"], 0);
+    assert_eq!(coordinator.jobs()[0].status, Some(JobStatus::Offered));
+}
+
+#[test]
+fn test_unstarted_job_is_requeued() {
+    let coordinator = Coordinator::in_memory().with_lease_duration(Duration::ZERO);
+    let mut scheduler = LocalScheduler::new(9029, 10, 10, 1);
+    coordinator.enqueue(scheduler.next_job().unwrap()).unwrap();
+    let api_url = start(&coordinator);
+
+    let get_job = || {
+        let mut resp = isahc::put(format!("{}/worker-nodes/get-job", api_url), "[]").unwrap();
+        let body: serde_json::Value = serde_json::from_str(&resp.text().unwrap()).unwrap();
+        body["id"].as_u64()
+    };
+    // Offered to a worker that never answers, then accepted by one that
+    // never starts it
+    assert_eq!(get_job(), Some(0));
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(get_job(), Some(0));
+    let req = isahc::Request::patch(format!("{}/worker-nodes/set-status", api_url))
+        .body("{\"id\": 0, \"status\": 3}")
+        .unwrap();
+    assert_eq!(isahc::send(req).unwrap().status().as_u16(), 200);
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(get_job(), Some(0));
+
+    let statuses: Vec<JobStatus> = coordinator.jobs()[0]
+        .history
+        .iter()
+        .map(|c| c.status)
+        .collect();
+    assert_eq!(
+        statuses,
+        vec![
+            JobStatus::Offered,
+            JobStatus::Requeued,
+            JobStatus::Offered,
+            JobStatus::Accepted,
+            JobStatus::Requeued,
+            JobStatus::Offered
+        ]
+    );
+}
+
+#[test]
+fn test_job_ranges_are_exact() {
+    let coordinator = Coordinator::in_memory();
+    let mut scheduler = LocalScheduler::new(9019, 10, 10, 1);
+    let mut job = scheduler.next_job().unwrap();
+    job.id = 16_777_217;
+    job.job_args.start_n = 100_000_000_000_000_000_001;
+    job.job_args.end_n = 100_000_000_000_000_000_011;
+    coordinator.enqueue(job).unwrap();
+    let api_url = start(&coordinator);
+
+    let mut resp = isahc::put(format!("{}/worker-nodes/get-job", api_url), "[]").unwrap();
+    let body: serde_json::Value = serde_json::from_str(&resp.text().unwrap()).unwrap();
+    assert_eq!(body["id"], 16_777_217);
+    assert_eq!(body["job_args"]["start_n"], "100000000000000000001");
+    assert_eq!(body["job_args"]["end_n"], "100000000000000000011");
+
+    let patch = |body: &str| {
+        let req = isahc::Request::patch(format!("{}/worker-nodes/set-status", api_url))
+            .body(body.to_string())
+            .unwrap();
+        isahc::send(req).unwrap().status().as_u16()
+    };
+    // Old workers send ids as floats, which are fine while they are exact
+    assert_eq!(patch("{\"id\": 16777217.5, \"status\": 3}"), 422);
+    assert_eq!(patch("{\"id\": 16777217.0, \"status\": 3}"), 200);
+    assert_eq!(coordinator.jobs()[0].status, Some(JobStatus::Accepted));
+}
+
+#[test]
+fn test_rejections_use_full_job_ids() {
+    let coordinator = Coordinator::in_memory();
+    // Job 256 needs more cores than any test machine has, and used to be
+    // excluded as job 0 instead of itself
+    let mut scheduler = LocalScheduler::new(9021, 10, 10, 100_000);
+    let mut unsuitable = scheduler.next_job().unwrap();
+    unsuitable.id = 256;
+    coordinator.enqueue(unsuitable).unwrap();
+    let mut scheduler = LocalScheduler::new(9021, 10, 10, 1);
+    coordinator.enqueue(scheduler.next_job().unwrap()).unwrap();
+    let api_url = start(&coordinator);
+
+    let mut sh = StatusHandler::from_config(worker_config(api_url, OUTPUT_DIR));
+    sh.get_job().unwrap();
+    let records = coordinator.jobs();
+    assert_eq!(records[0].status, Some(JobStatus::Rejected));
+    assert_eq!(records[1].status, Some(JobStatus::Accepted));
+}
+
+#[test]
+fn test_rejections_are_bounded() {
+    let coordinator = Coordinator::in_memory();
+    let mut scheduler = LocalScheduler::new(9022, 30, 10, 100_000);
+    while let Some(job) = scheduler.next_job() {
+        coordinator.enqueue(job).unwrap();
+    }
+    let api_url = start(&coordinator);
+
+    let mut sh = StatusHandler::from_config(WorkerConfig {
+        max_rejections: 2,
+        rejection_backoff_ms: 0,
+        ..worker_config(api_url, OUTPUT_DIR)
+    });
+    let error = sh.get_job().unwrap_err();
+    assert!(
+        matches!(error, StatusHandlerError::TooManyRejections(2)),
+        "{:?}",
+        error
+    );
+    let statuses: Vec<Option<JobStatus>> = coordinator.jobs().iter().map(|r| r.status).collect();
+    assert_eq!(
+        statuses,
+        vec![Some(JobStatus::Rejected), Some(JobStatus::Rejected), None]
+    );
+}
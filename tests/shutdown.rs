@@ -0,0 +1,87 @@
+//! Sends this test process a real SIGTERM, so it runs as its own binary.
+#![cfg(unix)]
+
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+use isahc::ReadResponseExt;
+
+use calculating_pi_rust::coordinator::Coordinator;
+use calculating_pi_rust::job_status::JobStatus;
+use calculating_pi_rust::pi_math::CalcPi;
+use calculating_pi_rust::scheduler::LocalScheduler;
+use calculating_pi_rust::shutdown;
+use calculating_pi_rust::status_handler::{StatusHandler, StatusHandlerError};
+
+mod common;
+use common::{start, worker_config};
+
+#[test]
+fn test_sigterm_pauses_job() {
+    let output_dir = "./testing/shutdown";
+    let coordinator = Coordinator::in_memory();
+    let mut scheduler = LocalScheduler::new(9018, 1_000_000, 1_000_000, 1);
+    coordinator.enqueue(scheduler.next_job().unwrap()).unwrap();
+    let api_url = start(&coordinator);
+
+    let watcher = coordinator.clone();
+    std::thread::spawn(move || {
+        while watcher.jobs()[0].status != Some(JobStatus::Running) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        std::thread::sleep(Duration::from_millis(200));
+        Command::new("kill")
+            .args(["-TERM", &std::process::id().to_string()])
+            .status()
+            .unwrap();
+    });
+    let mut sh = StatusHandler::from_config(worker_config(api_url.clone(), output_dir));
+    sh.get_job().unwrap();
+    let error = sh.dispatch_job().unwrap_err();
+    assert!(
+        matches!(error, StatusHandlerError::JobPaused),
+        "{:?}",
+        error
+    );
+    sh.report_failure(&error).unwrap();
+
+    let record = &coordinator.jobs()[0];
+    assert_eq!(record.status, Some(JobStatus::Paused));
+    assert_eq!(record.history.len(), 4);
+
+    let checkpoint_path = Path::new(output_dir).join("checkpoint_9018_0.json");
+    let calc_pi = CalcPi::resume_from_checkpoint(checkpoint_path.to_str().unwrap());
+    assert!(calc_pi.is_ok());
+
+    // A paused job is offered again
+    let mut resp = isahc::put(format!("{}/worker-nodes/get-job", api_url), "[]").unwrap();
+    assert_eq!(resp.status().as_u16(), 200);
+    resp.text().unwrap();
+    assert_eq!(coordinator.jobs()[0].status, Some(JobStatus::Offered));
+    std::fs::remove_dir_all(output_dir).unwrap();
+}
+
+/// Run by `test_sigterm_between_jobs_exits` in a process of its own: listens
+/// as a job would, stops, and then is signalled with nothing listening.
+#[test]
+#[ignore]
+fn sigterm_between_jobs() {
+    drop(shutdown::listen());
+    Command::new("kill")
+        .args(["-TERM", &std::process::id().to_string()])
+        .status()
+        .unwrap();
+    std::thread::sleep(Duration::from_secs(30));
+}
+
+#[test]
+fn test_sigterm_between_jobs_exits() {
+    let started = std::time::Instant::now();
+    let status = Command::new(std::env::current_exe().unwrap())
+        .args(["sigterm_between_jobs", "--exact", "--ignored", "--quiet"])
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(128 + 15));
+    assert!(started.elapsed() < Duration::from_secs(30));
+}